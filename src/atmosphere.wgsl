@@ -0,0 +1,17 @@
+// Glow around the limb of a planet, stronger where the shell is seen edge on
+
+#import bevy_pbr::{
+    forward_io::VertexOutput,
+    mesh_view_bindings::view,
+}
+
+@group(2) @binding(0) var<uniform> color: vec4<f32>;
+@group(2) @binding(1) var<uniform> power: f32;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let view_direction = normalize(view.world_position - in.world_position.xyz);
+    let rim = 1.0 - abs(dot(normalize(in.world_normal), view_direction));
+
+    return vec4(color.rgb, color.a * pow(rim, power));
+}
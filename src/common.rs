@@ -1,27 +1,44 @@
+use std::{fs, path::PathBuf};
+
 use bevy::{app::AppExit, asset::AssetMetaCheck, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{Action, ActionState},
+    player::MOUSE_SENSITIVITY,
+};
+
+const APP_NAME: &str = "playground";
+const SETTINGS_FILE: &str = "settings.ron";
+/// Lowest values the menu allows, loaded settings are brought back above them too
+pub const MIN_MOUSE_SENSITIVITY: f32 = 0.05;
+pub const MIN_BASE_SPEED: f32 = 50.;
+pub const MIN_GAME_SPEED: f32 = 0.25;
 
 pub struct CommonPlugin;
 
 impl Plugin for CommonPlugin {
     fn build(&self, app: &mut App) {
+        let settings = load_settings();
+
         app //
-            .insert_resource(ScreenMode::Windowed)
+            .insert_resource(settings.screen_mode)
+            .insert_resource(settings)
             .insert_resource(AssetMetaCheck::Never) // ??
             .add_systems(
                 Update,
-                (screen_mode_with_resource, set_screen_mode_with_keys),
-            );
+                (
+                    screen_mode_with_resource,
+                    set_screen_mode_with_keys,
+                    screen_mode_to_settings,
+                ),
+            )
+            .add_systems(Last, save_settings);
 
         #[cfg(debug_assertions)]
         {
-            // add debug_exit_with_ctrl_w system to debug mode
-            app.add_systems(
-                Update,
-                (
-                    bevy::window::close_on_esc,
-                    // debug_exit_with_ctrl_w
-                ),
-            );
+            // Esc opens the pause menu now
+            // app.add_systems(Update, (bevy::window::close_on_esc, debug_exit_with_ctrl_w));
 
             // use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
             // app.add_plugins((
@@ -35,12 +52,202 @@ impl Plugin for CommonPlugin {
 
 // Config
 // WindowMode
-#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum ScreenMode {
     Windowed,
     BorderlessFullscreen,
 }
 
+/// User preferences, persisted to the config directory whenever they change
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub screen_mode: ScreenMode,
+    pub mouse_sensitivity: f32,
+    pub invert_y: bool,
+    /// Free-fly camera speed, world units per second
+    pub base_speed: f32,
+    /// Game speed at launch and after a reset
+    pub default_game_speed: f32,
+    /// Appends the body events to a file in the data directory
+    pub log_events_to_file: bool,
+    pub hud: HudSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HudSettings {
+    pub show_fps: bool,
+    pub show_speed: bool,
+    pub show_clock: bool,
+    pub show_bodies: bool,
+    pub show_physics: bool,
+    pub show_labels: bool,
+    pub show_minimap: bool,
+    pub show_event_log: bool,
+    /// Only while flying a ship
+    pub show_ship: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            screen_mode: ScreenMode::Windowed,
+            mouse_sensitivity: MOUSE_SENSITIVITY,
+            invert_y: false,
+            base_speed: 500.,
+            default_game_speed: 1.,
+            log_events_to_file: false,
+            hud: HudSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Same settings with the numbers brought back in range, the file can be edited by hand.
+    /// Values that aren't finite fall back to the default.
+    fn validated(mut self) -> Self {
+        let defaults = Settings::default();
+        for (name, value, min, default) in [
+            (
+                "mouse_sensitivity",
+                &mut self.mouse_sensitivity,
+                MIN_MOUSE_SENSITIVITY,
+                defaults.mouse_sensitivity,
+            ),
+            (
+                "base_speed",
+                &mut self.base_speed,
+                MIN_BASE_SPEED,
+                defaults.base_speed,
+            ),
+            (
+                "default_game_speed",
+                &mut self.default_game_speed,
+                MIN_GAME_SPEED,
+                defaults.default_game_speed,
+            ),
+        ] {
+            let valid = if value.is_finite() {
+                value.max(min)
+            } else {
+                default
+            };
+            if valid != *value {
+                warn!("Setting {name} {value} out of range, using {valid}");
+                *value = valid;
+            }
+        }
+
+        self
+    }
+}
+
+impl Default for HudSettings {
+    fn default() -> Self {
+        HudSettings {
+            show_fps: true,
+            show_speed: true,
+            show_clock: true,
+            show_bodies: false,
+            show_physics: false,
+            show_labels: true,
+            show_minimap: true,
+            show_event_log: true,
+            show_ship: true,
+        }
+    }
+}
+
+fn load_settings() -> Settings {
+    let Some(path) = config_path(SETTINGS_FILE) else {
+        return Settings::default();
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => {
+            info!("No settings at {}, using defaults", path.display());
+            let settings = Settings::default();
+            write_settings(&settings);
+            return settings;
+        }
+    };
+
+    match ron::from_str::<Settings>(&contents) {
+        Ok(settings) => settings.validated(),
+        Err(err) => {
+            // Keep the broken file around instead of silently overwriting it
+            let backup = path.with_extension("ron.corrupt");
+            warn!(
+                "Invalid settings at {}, using defaults and moving it to {}: {err}",
+                path.display(),
+                backup.display()
+            );
+            if let Err(err) = fs::rename(&path, &backup) {
+                warn!("Could not move {}: {err}", path.display());
+            }
+
+            let settings = Settings::default();
+            write_settings(&settings);
+            settings
+        }
+    }
+}
+
+fn write_settings(settings: &Settings) {
+    let Some(path) = config_path(SETTINGS_FILE) else {
+        return;
+    };
+
+    let contents = match ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default()) {
+        Ok(contents) => contents,
+        Err(err) => {
+            warn!("Could not serialize settings: {err}");
+            return;
+        }
+    };
+
+    // Write to a temporary file first so a crash never leaves a half written file
+    let tmp_path = path.with_extension("ron.tmp");
+    if let Err(err) = fs::write(&tmp_path, contents).and_then(|_| fs::rename(&tmp_path, &path)) {
+        warn!("Could not save settings to {}: {err}", path.display());
+    }
+}
+
+fn save_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        write_settings(&settings);
+    }
+}
+
+fn screen_mode_to_settings(screen_mode: Res<ScreenMode>, mut settings: ResMut<Settings>) {
+    if screen_mode.is_changed() && settings.screen_mode != *screen_mode {
+        settings.screen_mode = *screen_mode;
+    }
+}
+
+/// Path of a file in the user's config directory, creating the directory if needed
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    app_path(dirs::config_dir()?, file_name)
+}
+
+/// Path of a file in the user's data directory, creating the directory if needed
+pub fn data_path(file_name: &str) -> Option<PathBuf> {
+    app_path(dirs::data_dir()?, file_name)
+}
+
+fn app_path(base_dir: PathBuf, file_name: &str) -> Option<PathBuf> {
+    let dir = base_dir.join(APP_NAME);
+
+    if let Err(err) = fs::create_dir_all(&dir) {
+        warn!("Could not create directory {}: {err}", dir.display());
+        return None;
+    }
+
+    Some(dir.join(file_name))
+}
+
 #[allow(dead_code)]
 fn debug_exit_with_ctrl_w(
     keyboard_input: Res<ButtonInput<KeyCode>>,
@@ -51,13 +258,8 @@ fn debug_exit_with_ctrl_w(
     }
 }
 
-fn set_screen_mode_with_keys(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut screen_mode: ResMut<ScreenMode>,
-) {
-    if (keyboard_input.pressed(KeyCode::AltLeft) && keyboard_input.just_pressed(KeyCode::Enter))
-        || keyboard_input.just_pressed(KeyCode::KeyF)
-    {
+fn set_screen_mode_with_keys(actions: Res<ActionState>, mut screen_mode: ResMut<ScreenMode>) {
+    if actions.just_pressed(Action::ToggleFullscreen) {
         match *screen_mode {
             ScreenMode::Windowed => {
                 *screen_mode = ScreenMode::BorderlessFullscreen;
@@ -80,3 +282,38 @@ fn screen_mode_with_resource(screen_mode: Res<ScreenMode>, mut windows: Query<&m
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_settings_are_kept() {
+        let settings = Settings {
+            mouse_sensitivity: 1.5,
+            base_speed: 2_000.,
+            default_game_speed: 3.,
+            ..default()
+        }
+        .validated();
+
+        assert_eq!(settings.mouse_sensitivity, 1.5);
+        assert_eq!(settings.base_speed, 2_000.);
+        assert_eq!(settings.default_game_speed, 3.);
+    }
+
+    #[test]
+    fn invalid_settings_are_brought_in_range() {
+        let settings = Settings {
+            mouse_sensitivity: f32::NAN,
+            base_speed: 0.,
+            default_game_speed: -2.,
+            ..default()
+        }
+        .validated();
+
+        assert_eq!(settings.mouse_sensitivity, MOUSE_SENSITIVITY);
+        assert_eq!(settings.base_speed, MIN_BASE_SPEED);
+        assert_eq!(settings.default_game_speed, MIN_GAME_SPEED);
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calculate_acceleration_velocity, end_physics_tick, motion::BodyMotion, AppState, GameSpeed,
+    PhysicsSettings, Planet, TIME_SPEED,
+};
+
+/// Density falls by e every this fraction of the atmosphere height
+const SCALE_HEIGHTS: f32 = 5.;
+/// Range of the linear medium rate in the menu, per second
+pub const MIN_DRAG_RATE: f32 = 1e-8;
+pub const MAX_DRAG_RATE: f32 = 1e-2;
+/// Range of the quadratic medium density in the menu, in kg/m³
+pub const MIN_GAS_DENSITY: f32 = 1e-3;
+pub const MAX_GAS_DENSITY: f32 = 1e5;
+
+pub struct DragPlugin;
+
+impl Plugin for DragPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .add_systems(
+                FixedUpdate,
+                (
+                    medium_drag.run_if(|physics: Res<PhysicsSettings>| physics.medium.is_some()),
+                    atmosphere_drag,
+                )
+                    .chain()
+                    .after(calculate_acceleration_velocity)
+                    .before(end_physics_tick)
+                    .run_if(in_state(AppState::Simulating)),
+            );
+    }
+}
+
+/// Fluid at rest filling the whole scene
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Medium {
+    /// Slows every body by the same fraction of its speed, `rate` per second
+    Linear { rate: f32 },
+    /// Pressure drag of a gas of `density` kg/m³, small and light bodies slow down faster
+    Quadratic { density: f32 },
+}
+
+impl Medium {
+    /// Following medium, with its default strength, to cycle through them in the menu
+    pub fn next(medium: Option<Self>) -> Option<Self> {
+        match medium {
+            None => Some(Medium::Linear { rate: 1e-5 }),
+            Some(Medium::Linear { .. }) => Some(Medium::Quadratic { density: 100. }),
+            Some(Medium::Quadratic { .. }) => None,
+        }
+    }
+}
+
+impl fmt::Display for Medium {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Medium::Linear { rate } => write!(f, "Linear drag {rate:.1e}/s"),
+            Medium::Quadratic { density } => write!(f, "Quadratic drag {density:.1e} kg/m³"),
+        }
+    }
+}
+
+/// Gas around a body that slows down smaller bodies passing through it. It turns with the body.
+/// The density falls off exponentially from the surface and ends at `height`.
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Atmosphere {
+    /// Top of the atmosphere above the surface, in radii of the body
+    pub height: f32,
+    /// kg/m³ at the surface, Earth is about 1.2
+    pub density: f32,
+}
+
+impl Atmosphere {
+    /// kg/m³ at `altitude` km above the surface of a body of `radius` km
+    fn density_at(&self, altitude: f32, radius: f32) -> f32 {
+        let height = self.height * radius;
+        if altitude > height {
+            return 0.;
+        }
+
+        self.density * (-altitude.max(0.) * SCALE_HEIGHTS / height).exp()
+    }
+}
+
+/// Factor k (1/km) of the deceleration k v² of a body in a gas of `density` kg/m³.
+/// A sphere with a drag coefficient of 1 slows by 3 ρ_gas v² / (8 ρ r).
+fn quadratic_drag(density: f32, planet: &Planet) -> f32 {
+    // kg/m³ over g/cm³ is 1e-3, the radius in km then gives km/s²
+    3. * density / (8. * planet.density * planet.radius) * 1e-3
+}
+
+/// Exact solution of dv/dt = -k |v| v over `dt`, stable whatever the step
+fn apply_quadratic_drag(velocity: Vec3, k: f32, dt: f32) -> Vec3 {
+    velocity / (1. + k * velocity.length() * dt)
+}
+
+/// Drag of the global medium, on every body moved by gravity
+fn medium_drag(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    physics: Res<PhysicsSettings>,
+    mut planets_q: Query<&mut Planet, Without<BodyMotion>>,
+) {
+    let Some(medium) = physics.medium else {
+        return;
+    };
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+
+    for mut planet in &mut planets_q {
+        planet.velocity = match medium {
+            Medium::Linear { rate } => planet.velocity * (-rate * dt).exp(),
+            Medium::Quadratic { density } => {
+                let k = quadratic_drag(density, &planet);
+                apply_quadratic_drag(planet.velocity, k, dt)
+            }
+        };
+    }
+}
+
+/// Quadratic drag of the atmospheres on the smaller bodies inside them, relative to the
+/// turning gas. The bodies with the atmosphere aren't slowed in return.
+fn atmosphere_drag(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    atmospheres_q: Query<(Entity, &Atmosphere)>,
+    mut planets_q: Query<(Entity, &mut Planet, Has<BodyMotion>)>,
+) {
+    if atmospheres_q.is_empty() {
+        return;
+    }
+
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+    let atmospheres: Vec<(Entity, Atmosphere, Planet)> = atmospheres_q
+        .iter()
+        .filter_map(|(entity, atmosphere)| {
+            let (_, planet, _) = planets_q.get(entity).ok()?;
+            Some((entity, *atmosphere, planet.clone()))
+        })
+        .collect();
+
+    for (entity, mut planet, fixed) in &mut planets_q {
+        if fixed {
+            continue;
+        }
+
+        for (owner, atmosphere, body) in &atmospheres {
+            if *owner == entity || planet.radius >= body.radius {
+                continue;
+            }
+
+            let offset = planet.position - body.position;
+            let density = atmosphere.density_at(offset.length() - body.radius, body.radius);
+            if density <= 0. {
+                continue;
+            }
+
+            let wind = body.velocity + body.angular_velocity.cross(offset);
+            let k = quadratic_drag(density, &planet);
+            planet.velocity = wind + apply_quadratic_drag(planet.velocity - wind, k, dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(radius: f32, density: f32) -> Planet {
+        Planet {
+            radius,
+            density,
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            acceleration: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+            angular_velocity: Vec3::ZERO,
+            charge: 0.,
+        }
+    }
+
+    #[test]
+    fn quadratic_drag_matches_the_drag_equation() {
+        // A 1 km rock of 3 g/cm³ at 10 km/s in a gas of 1.2 kg/m³, in SI units
+        let (radius, density, gas, speed) = (1_000_f64, 3_000_f64, 1.2_f64, 10_000_f64);
+        let area = std::f64::consts::PI * radius.powi(2);
+        let mass = 4. / 3. * std::f64::consts::PI * radius.powi(3) * density;
+        // F = ½ C ρ v² A with C = 1, m/s² to km/s²
+        let deceleration = 0.5 * gas * speed.powi(2) * area / mass * 1e-3;
+
+        let k = quadratic_drag(1.2, &body(1., 3.));
+        let expected = deceleration / (speed * 1e-3).powi(2);
+        assert!((f64::from(k) - expected).abs() < 1e-6 * expected);
+    }
+
+    #[test]
+    fn smaller_and_lighter_bodies_slow_down_faster() {
+        let k = quadratic_drag(1., &body(10., 3.));
+
+        assert!((quadratic_drag(1., &body(5., 3.)) - 2. * k).abs() < 1e-6 * k);
+        assert!((quadratic_drag(1., &body(10., 1.5)) - 2. * k).abs() < 1e-6 * k);
+        assert!((quadratic_drag(2., &body(10., 3.)) - 2. * k).abs() < 1e-6 * k);
+    }
+
+    #[test]
+    fn quadratic_drag_step_is_exact() {
+        let (velocity, k, dt) = (Vec3::new(3., -4., 0.), 0.02, 50.);
+
+        let mut stepped = velocity;
+        for _ in 0..100_000 {
+            stepped -= k * stepped.length() * stepped * (dt / 100_000.);
+        }
+
+        let exact = apply_quadratic_drag(velocity, k, dt);
+        assert!(exact.distance(stepped) < 1e-3);
+        // Slows down, never turns around, however large the step
+        let huge = apply_quadratic_drag(velocity, k, 1e9);
+        assert!(huge.length() < 1e-6 && huge.dot(velocity) >= 0.);
+    }
+
+    #[test]
+    fn atmosphere_thins_out_and_ends() {
+        let atmosphere = Atmosphere {
+            height: 0.1,
+            density: 1.2,
+        };
+
+        assert_eq!(atmosphere.density_at(0., 6_000.), 1.2);
+        assert_eq!(atmosphere.density_at(-5., 6_000.), 1.2);
+        assert!(atmosphere.density_at(100., 6_000.) < 1.2);
+        assert!(atmosphere.density_at(599., 6_000.) > 0.);
+        assert_eq!(atmosphere.density_at(601., 6_000.), 0.);
+    }
+}
@@ -0,0 +1,292 @@
+use bevy::{
+    core_pipeline::Skybox,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+        },
+    },
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::MainCamera,
+    utils::{fbm, uv_debug_texture},
+};
+
+const CUBEMAP_SIZE: usize = 512; // pixels per face side
+const STAR_COUNT: usize = 12_000;
+const STARFIELD_SEED: u64 = 7;
+/// Tilt of the Milky Way band against the horizontal plane
+const BAND_TILT: f32 = 1.1; // radians
+const BAND_WIDTH: f32 = 0.18; // sine of the angular half width
+/// Luminance of a white skybox pixel, in cd/m²
+const SKYBOX_BRIGHTNESS: f32 = 1000.;
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<Environment>()
+            .init_resource::<Starfields>()
+            .add_systems(Startup, (setup_lab, generate_starfields))
+            .add_systems(
+                Update,
+                (
+                    receive_starfields
+                        .run_if(|starfields: Res<Starfields>| !starfields.tasks.is_empty()),
+                    apply_environment.run_if(
+                        resource_changed::<Environment>.or_else(resource_changed::<Starfields>),
+                    ),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Surroundings of the bodies, chosen by the scene
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Environment {
+    /// Starfield skybox and nothing else
+    Space { milky_way: bool },
+    /// Textured floor and ceiling planes, useful as a reference grid
+    #[default]
+    Lab,
+}
+
+/// Debug textured ground and ceiling, only shown in the lab and hidden by visualizations
+/// that need the space
+#[derive(Component)]
+pub struct LabPlane;
+
+/// Starfield cubemaps, generated in the background from startup since they take a while
+#[derive(Resource, Default)]
+struct Starfields {
+    plain: Option<Handle<Image>>,
+    milky_way: Option<Handle<Image>>,
+    /// Cubemaps still being generated, with or without the Milky Way
+    tasks: Vec<(bool, Task<Image>)>,
+}
+
+fn setup_lab(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let debug_material = materials.add(StandardMaterial {
+        base_color_texture: Some(images.add(uv_debug_texture())),
+        cull_mode: None,
+        specular_transmission: 0.9,
+        diffuse_transmission: 1.0,
+        thickness: 1.8,
+        ior: 1.5,
+        perceptual_roughness: 0.12,
+        ..default()
+    });
+    let plane = meshes.add(Plane3d::default().mesh().size(10000.0, 10000.0));
+
+    // Ground and ceiling
+    for height in [-500., 2500.] {
+        commands.spawn((
+            PbrBundle {
+                mesh: plane.clone(),
+                material: debug_material.clone(),
+                transform: Transform::from_xyz(0., height, 0.),
+                ..default()
+            },
+            LabPlane,
+        ));
+    }
+}
+
+fn generate_starfields(mut starfields: ResMut<Starfields>) {
+    let pool = AsyncComputeTaskPool::get();
+    starfields.tasks = [false, true]
+        .into_iter()
+        .map(|milky_way| {
+            (
+                milky_way,
+                pool.spawn(async move { starfield_cubemap(milky_way) }),
+            )
+        })
+        .collect();
+}
+
+/// Stores the finished cubemaps, `apply_environment` then picks them up
+fn receive_starfields(mut starfields: ResMut<Starfields>, mut images: ResMut<Assets<Image>>) {
+    // Polling doesn't count as a change, only a finished cubemap does
+    let mut finished = false;
+    let inner = starfields.bypass_change_detection();
+    inner.tasks.retain_mut(|(milky_way, task)| {
+        let Some(image) = block_on(poll_once(task)) else {
+            return true;
+        };
+
+        let handle = Some(images.add(image));
+        if *milky_way {
+            inner.milky_way = handle;
+        } else {
+            inner.plain = handle;
+        }
+        finished = true;
+        false
+    });
+
+    if finished {
+        starfields.set_changed();
+    }
+}
+
+/// Swaps the skybox of the main camera, the planes follow in `field::field_visibility`.
+/// Space stays black until its starfield is ready.
+fn apply_environment(
+    mut commands: Commands,
+    environment: Res<Environment>,
+    starfields: Res<Starfields>,
+    camera_q: Query<Entity, With<MainCamera>>,
+) {
+    let Ok(camera) = camera_q.get_single() else {
+        return;
+    };
+
+    match *environment {
+        Environment::Space { milky_way } => {
+            let starfield = if milky_way {
+                &starfields.milky_way
+            } else {
+                &starfields.plain
+            };
+
+            match starfield {
+                Some(image) => commands.entity(camera).insert(Skybox {
+                    image: image.clone(),
+                    brightness: SKYBOX_BRIGHTNESS,
+                }),
+                None => commands.entity(camera).remove::<Skybox>(),
+            };
+        }
+        Environment::Lab => {
+            commands.entity(camera).remove::<Skybox>();
+        }
+    }
+}
+
+/// Direction through the center of a pixel of a cubemap face, faces in +X, -X, +Y, -Y, +Z,
+/// -Z order
+fn cubemap_direction(face: usize, x: usize, y: usize) -> Vec3 {
+    let s = 2. * (x as f32 + 0.5) / CUBEMAP_SIZE as f32 - 1.;
+    let t = 2. * (y as f32 + 0.5) / CUBEMAP_SIZE as f32 - 1.;
+
+    match face {
+        0 => Vec3::new(1., -t, -s),
+        1 => Vec3::new(-1., -t, s),
+        2 => Vec3::new(s, 1., t),
+        3 => Vec3::new(s, -1., -t),
+        4 => Vec3::new(s, -t, 1.),
+        _ => Vec3::new(-s, -t, -1.),
+    }
+    .normalize()
+}
+
+/// Inverse of [`cubemap_direction`], the face and pixel a direction falls on
+fn cubemap_pixel(direction: Vec3) -> (usize, usize, usize) {
+    let abs = direction.abs();
+    let (face, s, t, major) = if abs.x >= abs.y && abs.x >= abs.z {
+        if direction.x > 0. {
+            (0, -direction.z, -direction.y, abs.x)
+        } else {
+            (1, direction.z, -direction.y, abs.x)
+        }
+    } else if abs.y >= abs.z {
+        if direction.y > 0. {
+            (2, direction.x, direction.z, abs.y)
+        } else {
+            (3, direction.x, -direction.z, abs.y)
+        }
+    } else if direction.z > 0. {
+        (4, direction.x, -direction.y, abs.z)
+    } else {
+        (5, -direction.x, -direction.y, abs.z)
+    };
+
+    let pixel = |coordinate: f32| {
+        (((coordinate / major + 1.) / 2. * CUBEMAP_SIZE as f32) as usize).min(CUBEMAP_SIZE - 1)
+    };
+    (face, pixel(s), pixel(t))
+}
+
+/// Random stars of varied brightness and temperature, over a faint Milky Way band if asked
+fn starfield_cubemap(milky_way: bool) -> Image {
+    let face_len = CUBEMAP_SIZE * CUBEMAP_SIZE;
+    let mut pixels = vec![Vec3::ZERO; 6 * face_len];
+    let band_normal = Quat::from_rotation_x(BAND_TILT) * Vec3::Y;
+
+    if milky_way {
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let face = i / face_len;
+            let (x, y) = (i % CUBEMAP_SIZE, i % face_len / CUBEMAP_SIZE);
+            let direction = cubemap_direction(face, x, y);
+
+            let latitude = direction.dot(band_normal) / BAND_WIDTH;
+            let dust = fbm(direction * 6., STARFIELD_SEED as u32, 5);
+            let glow = (-latitude * latitude).exp() * (0.3 + 0.7 * dust);
+            *pixel = Vec3::new(0.09, 0.08, 0.1) * glow;
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(STARFIELD_SEED);
+    for _ in 0..STAR_COUNT {
+        let Some(mut direction) = Vec3::new(
+            rng.gen_range(-1. ..1.),
+            rng.gen_range(-1. ..1.),
+            rng.gen_range(-1. ..1.),
+        )
+        .try_normalize() else {
+            continue;
+        };
+        // Stars crowd along the band
+        if milky_way && rng.gen_bool(0.4) {
+            let offset = direction.dot(band_normal);
+            direction = (direction - band_normal * offset * 0.8).normalize();
+        }
+
+        // Most stars are faint, a few are bright
+        let brightness = rng.gen::<f32>().powi(6) * 0.9 + 0.1;
+        let temperature = rng.gen::<f32>();
+        let color = Vec3::new(1., 0.85, 0.7).lerp(Vec3::new(0.7, 0.8, 1.), temperature);
+
+        let (face, x, y) = cubemap_pixel(direction);
+        pixels[face * face_len + y * CUBEMAP_SIZE + x] += color * brightness;
+    }
+
+    let data = pixels
+        .iter()
+        .flat_map(|pixel| {
+            let pixel = (pixel.clamp(Vec3::ZERO, Vec3::ONE) * 255.).round();
+            [pixel.x as u8, pixel.y as u8, pixel.z as u8, 255]
+        })
+        .collect();
+
+    let mut image = Image::new(
+        Extent3d {
+            width: CUBEMAP_SIZE as u32,
+            height: (6 * CUBEMAP_SIZE) as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.reinterpret_stacked_2d_as_array(6);
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..default()
+    });
+    image
+}
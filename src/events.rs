@@ -0,0 +1,283 @@
+use std::{collections::VecDeque, fs::OpenOptions, io::Write};
+
+use bevy::{
+    math::DVec3,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    calculate_acceleration_velocity,
+    common::{data_path, Settings},
+    end_physics_tick,
+    motion::BodyMotion,
+    AppState, PhysicsSettings, Planet, SimStats,
+};
+
+/// Entries kept in the log, older ones are dropped
+const LOG_CAPACITY: usize = 500;
+const LOG_FILE: &str = "events.log";
+
+pub struct BodyEventsPlugin;
+
+impl Plugin for BodyEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .add_event::<BodyCollision>()
+            .add_event::<BodyMerged>()
+            .add_event::<BodyEscaped>()
+            .add_event::<BodyCreated>()
+            .add_event::<SceneLoaded>()
+            .init_resource::<EventLog>()
+            .add_systems(OnEnter(AppState::Loading), clear_log)
+            .add_systems(
+                FixedUpdate,
+                detect_escapes
+                    .after(calculate_acceleration_velocity)
+                    .before(end_physics_tick)
+                    .run_if(in_state(AppState::Simulating)),
+            )
+            .add_systems(Update, (detect_created_bodies, record_events).chain());
+    }
+}
+
+/// Two bodies started touching
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BodyCollision {
+    pub first: Entity,
+    pub second: Entity,
+    /// Relative speed, in km/s
+    pub impact_speed: f32,
+    /// Kinetic energy of the relative motion, in J
+    pub energy: f64,
+}
+
+/// `absorbed` merged into `survivor`, see `merge_bodies`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BodyMerged {
+    pub survivor: Entity,
+    pub absorbed: Entity,
+    /// Relative speed, in km/s
+    pub impact_speed: f32,
+    /// Kinetic energy of the relative motion, in J
+    pub energy: f64,
+}
+
+/// A body is on an unbound path away from the rest of the system
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BodyEscaped {
+    pub entity: Entity,
+    /// Speed left far away from the system, in km/s
+    pub excess_speed: f32,
+}
+
+/// A body was added to the simulation
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BodyCreated {
+    pub entity: Entity,
+}
+
+/// The bodies of a scene were replaced, they don't count as created
+#[derive(Event, Debug, Clone)]
+pub struct SceneLoaded {
+    pub name: String,
+}
+
+/// Relative speed (km/s) and kinetic energy of the relative motion (J) of two bodies
+pub fn impact(first: &Planet, second: &Planet) -> (f32, f64) {
+    let speed = first.velocity.distance(second.velocity);
+    let (m1, m2) = (f64::from(first.mass()), f64::from(second.mass()));
+    // Reduced mass, km²/s² to m²/s²
+    let energy = 0.5 * m1 * m2 / (m1 + m2) * f64::from(speed).powi(2) * 1e6;
+
+    (speed, energy)
+}
+
+pub struct LogEntry {
+    /// Simulation time, in seconds
+    pub time: f64,
+    pub message: String,
+}
+
+/// Recent body events, newest last
+#[derive(Resource, Default)]
+pub struct EventLog {
+    pub entries: VecDeque<LogEntry>,
+}
+
+fn clear_log(mut log: ResMut<EventLog>) {
+    log.entries.clear();
+}
+
+/// Bodies appearing while simulating, like fragments, the ship and spawned bodies. Runs in
+/// every state so the ones a scene spawns while loading are seen, and skipped, right away.
+fn detect_created_bodies(
+    state: Res<State<AppState>>,
+    mut created: EventWriter<BodyCreated>,
+    planets_q: Query<Entity, Added<Planet>>,
+) {
+    if *state.get() != AppState::Simulating {
+        return;
+    }
+
+    created.send_batch(planets_q.iter().map(|entity| BodyCreated { entity }));
+}
+
+/// Newtonian energy of each body relative to the barycenter of all the others. Reported once,
+/// and again only after the body was bound in between.
+fn detect_escapes(
+    physics: Res<PhysicsSettings>,
+    mut escaped: Local<HashSet<Entity>>,
+    mut escapes: EventWriter<BodyEscaped>,
+    planets_q: Query<(Entity, &Planet, Has<BodyMotion>)>,
+) {
+    // f64, star masses times km overflow f32
+    let (total_mass, weighted_position, weighted_velocity) = planets_q.iter().fold(
+        (0., DVec3::ZERO, DVec3::ZERO),
+        |(mass, position, velocity), (_, planet, _)| {
+            let m = f64::from(planet.mass());
+            (
+                mass + m,
+                position + planet.position.as_dvec3() * m,
+                velocity + planet.velocity.as_dvec3() * m,
+            )
+        },
+    );
+
+    for (entity, planet, fixed) in &planets_q {
+        let m = f64::from(planet.mass());
+        let others_mass = total_mass - m;
+        if fixed || others_mass <= 0. {
+            continue;
+        }
+
+        let offset = planet.position.as_dvec3()
+            - (weighted_position - planet.position.as_dvec3() * m) / others_mass;
+        let velocity = planet.velocity.as_dvec3()
+            - (weighted_velocity - planet.velocity.as_dvec3() * m) / others_mass;
+        let energy = 0.5 * velocity.length_squared()
+            - f64::from(physics.gravity.constant) * others_mass / offset.length();
+
+        if energy <= 0. || offset.dot(velocity) <= 0. {
+            escaped.remove(&entity);
+            continue;
+        }
+        if escaped.insert(entity) {
+            escapes.send(BodyEscaped {
+                entity,
+                excess_speed: (2. * energy).sqrt() as f32,
+            });
+        }
+    }
+}
+
+/// Writes the body events to the log, and to a file in the data directory when enabled.
+/// Each scene starts a new section of the file.
+#[allow(clippy::too_many_arguments)]
+fn record_events(
+    settings: Res<Settings>,
+    stats: Res<SimStats>,
+    mut log: ResMut<EventLog>,
+    mut names: Local<HashMap<Entity, String>>,
+    mut scenes: EventReader<SceneLoaded>,
+    mut collisions: EventReader<BodyCollision>,
+    mut merges: EventReader<BodyMerged>,
+    mut escapes: EventReader<BodyEscaped>,
+    mut creations: EventReader<BodyCreated>,
+    names_q: Query<(Entity, &Name), With<Planet>>,
+) {
+    // Merged bodies are gone by now, their name is remembered from the last frame
+    names.extend(
+        names_q
+            .iter()
+            .map(|(entity, name)| (entity, name.to_string())),
+    );
+    let name = |entity: Entity| {
+        names
+            .get(&entity)
+            .cloned()
+            .unwrap_or_else(|| format!("{entity:?}"))
+    };
+
+    let mut messages: Vec<String> = Vec::new();
+    messages.extend(
+        creations
+            .read()
+            .map(|event| format!("{} appeared", name(event.entity))),
+    );
+    messages.extend(collisions.read().map(|event| {
+        format!(
+            "{} hit {} at {:.2} km/s, {:.2e} J",
+            name(event.first),
+            name(event.second),
+            event.impact_speed,
+            event.energy
+        )
+    }));
+    messages.extend(merges.read().map(|event| {
+        format!(
+            "{} absorbed {} at {:.2} km/s, {:.2e} J",
+            name(event.survivor),
+            name(event.absorbed),
+            event.impact_speed,
+            event.energy
+        )
+    }));
+    messages.extend(escapes.read().map(|event| {
+        format!(
+            "{} is escaping at {:.2} km/s",
+            name(event.entity),
+            event.excess_speed
+        )
+    }));
+
+    names.clear();
+    names.extend(
+        names_q
+            .iter()
+            .map(|(entity, name)| (entity, name.to_string())),
+    );
+
+    if settings.log_events_to_file {
+        let lines: Vec<String> = scenes
+            .read()
+            .map(|scene| format!("\n=== {} ===", scene.name))
+            .chain(
+                messages
+                    .iter()
+                    .map(|message| format!("[{:.0} s] {message}", stats.elapsed)),
+            )
+            .collect();
+        append_to_log_file(&lines);
+    } else {
+        scenes.clear();
+    }
+
+    for message in messages {
+        log.entries.push_back(LogEntry {
+            time: stats.elapsed,
+            message,
+        });
+    }
+    while log.entries.len() > LOG_CAPACITY {
+        log.entries.pop_front();
+    }
+}
+
+fn append_to_log_file(lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+    let Some(path) = data_path(LOG_FILE) else {
+        return;
+    };
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| lines.iter().try_for_each(|line| writeln!(file, "{line}")));
+    if let Err(err) = result {
+        warn!("Could not write the event log to {}: {err}", path.display());
+    }
+}
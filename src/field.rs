@@ -0,0 +1,289 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+        view::NoFrustumCulling,
+    },
+};
+
+use crate::{
+    environment::{Environment, LabPlane},
+    input::{Action, ActionState},
+    view::ViewScale,
+    PhysicsSettings, Planet,
+};
+
+const GRID_RESOLUTION: usize = 96; // vertices per side
+const SHEET_DEPTH: f32 = 0.25; // deepest well, as a fraction of the grid size
+const ARROWS_PER_SIDE: usize = 8;
+const ARROW_LENGTH: f32 = 0.9; // longest arrow, as a fraction of the arrow spacing
+/// Empty space around the bodies, as a fraction of their extent
+const FIELD_PADDING: f32 = 0.5;
+const MIN_FIELD_EXTENT: f32 = 100.; // world units
+
+pub struct FieldPlugin;
+
+impl Plugin for FieldPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<FieldView>()
+            .add_systems(Startup, setup_field_grid)
+            .add_systems(
+                Update,
+                (
+                    toggle_field_view,
+                    field_visibility.run_if(
+                        resource_changed::<FieldView>.or_else(resource_changed::<Environment>),
+                    ),
+                    update_field_grid,
+                    draw_field_arrows,
+                ),
+            );
+    }
+}
+
+/// Gravity field visualizations currently shown
+#[derive(Resource, Default)]
+pub struct FieldView {
+    /// Rubber sheet deformed by the potential on the horizontal plane of the bodies
+    pub grid: bool,
+    /// Acceleration arrows on a 3D lattice around the bodies
+    pub arrows: bool,
+}
+
+#[derive(Component)]
+struct FieldGrid;
+
+fn setup_field_grid(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let n = GRID_RESOLUTION as u32;
+    let mut indices = Vec::new();
+    for row in 0..n {
+        for column in 0..n - 1 {
+            indices.extend([row * n + column, row * n + column + 1]);
+            indices.extend([column * n + row, (column + 1) * n + row]);
+        }
+    }
+
+    // Positions and colors are filled every frame from the bodies
+    let mesh = Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::default())
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0., 0., 0.]; GRID_RESOLUTION * GRID_RESOLUTION],
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            vec![[1., 1., 1., 1.]; GRID_RESOLUTION * GRID_RESOLUTION],
+        )
+        .with_inserted_indices(Indices::U32(indices));
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(mesh),
+            material: materials.add(StandardMaterial {
+                base_color: Color::WHITE,
+                unlit: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        // The bounds change every frame
+        NoFrustumCulling,
+        FieldGrid,
+    ));
+}
+
+fn toggle_field_view(actions: Res<ActionState>, mut field_view: ResMut<FieldView>) {
+    if actions.just_pressed(Action::ToggleFieldGrid) {
+        field_view.grid = !field_view.grid;
+    }
+
+    if actions.just_pressed(Action::ToggleFieldArrows) {
+        field_view.arrows = !field_view.arrows;
+    }
+}
+
+fn field_visibility(
+    field_view: Res<FieldView>,
+    environment: Res<Environment>,
+    mut grid_q: Query<&mut Visibility, (With<FieldGrid>, Without<LabPlane>)>,
+    mut planes_q: Query<&mut Visibility, (With<LabPlane>, Without<FieldGrid>)>,
+) {
+    let visibility = |show: bool| {
+        if show {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        }
+    };
+
+    for mut grid_visibility in &mut grid_q {
+        *grid_visibility = visibility(field_view.grid);
+    }
+
+    // The planes would cut through the field
+    let show_planes = *environment == Environment::Lab && !field_view.grid && !field_view.arrows;
+    for mut plane_visibility in &mut planes_q {
+        *plane_visibility = visibility(show_planes);
+    }
+}
+
+/// World space box around the bodies that the field is sampled in, as center and half size
+fn field_bounds<'a>(
+    view_scale: &ViewScale,
+    planets: impl IntoIterator<Item = &'a Planet>,
+) -> (Vec3, f32) {
+    view_scale
+        .bounds(planets)
+        .map_or((Vec3::ZERO, MIN_FIELD_EXTENT), |(min, max)| {
+            let half_size = ((max - min) / 2.).max_element() * (1. + FIELD_PADDING);
+            ((min + max) / 2., half_size.max(MIN_FIELD_EXTENT))
+        })
+}
+
+fn update_field_grid(
+    field_view: Res<FieldView>,
+    view_scale: Res<ViewScale>,
+    physics: Res<PhysicsSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    grid_q: Query<&Handle<Mesh>, With<FieldGrid>>,
+    planets_q: Query<&Planet>,
+) {
+    if !field_view.grid {
+        return;
+    }
+    let Some(mesh) = grid_q
+        .get_single()
+        .ok()
+        .and_then(|mesh| meshes.get_mut(mesh))
+    else {
+        return;
+    };
+
+    let (center, half_size) = field_bounds(&view_scale, &planets_q);
+    let bodies: Vec<(Vec3, f32, f32)> = planets_q
+        .iter()
+        .map(|planet| (planet.position, planet.mass(), planet.radius))
+        .collect();
+
+    let spacing = 2. * half_size / (GRID_RESOLUTION - 1) as f32;
+    let points: Vec<Vec3> = (0..GRID_RESOLUTION * GRID_RESOLUTION)
+        .map(|i| {
+            let (row, column) = (i / GRID_RESOLUTION, i % GRID_RESOLUTION);
+            Vec3::new(
+                center.x - half_size + column as f32 * spacing,
+                center.y,
+                center.z - half_size + row as f32 * spacing,
+            )
+        })
+        .collect();
+    let potentials: Vec<f32> = points
+        .iter()
+        .map(|&point| {
+            let position = view_scale.to_physical(point);
+            bodies
+                .iter()
+                .map(|&(source, mass, radius)| {
+                    physics.gravity.potential(position, source, mass, radius)
+                })
+                .sum()
+        })
+        .collect();
+
+    // Deepest well reaches `SHEET_DEPTH`, whatever the masses involved
+    let min_potential = potentials.iter().copied().fold(0., f32::min);
+    let depth = |potential: f32| {
+        if min_potential < 0. {
+            potential / min_potential
+        } else {
+            0.
+        }
+    };
+
+    let positions: Vec<[f32; 3]> = points
+        .iter()
+        .zip(&potentials)
+        .map(|(point, &potential)| {
+            let y = point.y - depth(potential) * SHEET_DEPTH * 2. * half_size;
+            [point.x, y, point.z]
+        })
+        .collect();
+    let colors: Vec<[f32; 4]> = potentials
+        .iter()
+        .map(|&potential| {
+            let t = depth(potential).sqrt();
+            Color::rgb(0.1 + 0.9 * t, 0.3 + 0.5 * t, 1.).as_linear_rgba_f32()
+        })
+        .collect();
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
+
+fn draw_field_arrows(
+    mut gizmos: Gizmos,
+    field_view: Res<FieldView>,
+    view_scale: Res<ViewScale>,
+    physics: Res<PhysicsSettings>,
+    planets_q: Query<&Planet>,
+) {
+    if !field_view.arrows {
+        return;
+    }
+
+    let (center, half_size) = field_bounds(&view_scale, &planets_q);
+    let spacing = 2. * half_size / (ARROWS_PER_SIDE - 1) as f32;
+
+    let arrows: Vec<(Vec3, Vec3)> = (0..ARROWS_PER_SIDE.pow(3))
+        .map(|i| {
+            let index = Vec3::new(
+                (i % ARROWS_PER_SIDE) as f32,
+                (i / ARROWS_PER_SIDE % ARROWS_PER_SIDE) as f32,
+                (i / ARROWS_PER_SIDE.pow(2)) as f32,
+            );
+            let point = center - half_size + index * spacing;
+            let position = view_scale.to_physical(point);
+
+            // No field inside the bodies, like colliding bodies in the simulation
+            let acceleration = planets_q
+                .iter()
+                .filter(|planet| position.distance(planet.position) > planet.radius)
+                .map(|planet| {
+                    physics
+                        .gravity
+                        .acceleration(position, planet.position, planet.mass())
+                })
+                .sum();
+
+            (point, acceleration)
+        })
+        .collect();
+
+    let max_acceleration = arrows
+        .iter()
+        .map(|(_, acceleration)| acceleration.length())
+        .fold(0., f32::max);
+    if max_acceleration <= 0. {
+        return;
+    }
+
+    for (point, acceleration) in arrows {
+        // Square root so the weak far field is still visible
+        let t = (acceleration.length() / max_acceleration).sqrt();
+        let length = t * ARROW_LENGTH * spacing;
+        if length <= f32::EPSILON {
+            continue;
+        }
+
+        gizmos.arrow(
+            point,
+            point + acceleration.normalize() * length,
+            Color::rgb(t, 0.4, 1. - t),
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt;
+
+use bevy::{log::warn, math::Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{COULOMB_CONSTANT, GRAVITY_CONSTANT};
+
+/// Shortest softening length, Yukawa range and reference distance, in km
+pub const MIN_LENGTH: f32 = 1.;
+/// Below 1 the potential no longer vanishes far away
+pub const MIN_EXPONENT: f32 = 1.25;
+pub const MAX_EXPONENT: f32 = 4.;
+/// The menu keeps G within a thousand times the real one either way
+pub const MIN_GRAVITY_CONSTANT: f32 = GRAVITY_CONSTANT * 1e-3;
+pub const MAX_GRAVITY_CONSTANT: f32 = GRAVITY_CONSTANT * 1e3;
+
+/// How the pull of a body falls off with distance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ForceLaw {
+    /// Inverse square
+    #[default]
+    Newtonian,
+    /// Inverse square with a softening `length` in km, finite at zero distance
+    Softened { length: f32 },
+    /// Inverse square screened beyond `range`, in km
+    Yukawa { range: f32 },
+    /// Falls off with the distance to the power `exponent`, equal to the inverse square at
+    /// `reference` km
+    InversePower { exponent: f32, reference: f32 },
+}
+
+impl ForceLaw {
+    /// Acceleration (km/s²) per unit of G m at `distance` km
+    fn strength(&self, distance: f32) -> f32 {
+        match *self {
+            ForceLaw::Newtonian => distance.powi(-2),
+            ForceLaw::Softened { length } => {
+                distance / (distance.powi(2) + length.powi(2)).powf(1.5)
+            }
+            ForceLaw::Yukawa { range } => {
+                (1. + distance / range) * (-distance / range).exp() / distance.powi(2)
+            }
+            ForceLaw::InversePower {
+                exponent,
+                reference,
+            } => (reference / distance).powf(exponent - 2.) / distance.powi(2),
+        }
+    }
+
+    /// Potential (km²/s²) per unit of G m at `distance` km, zero far away
+    fn potential(&self, distance: f32) -> f32 {
+        match *self {
+            ForceLaw::Newtonian => -1. / distance,
+            ForceLaw::Softened { length } => -1. / (distance.powi(2) + length.powi(2)).sqrt(),
+            ForceLaw::Yukawa { range } => -(-distance / range).exp() / distance,
+            ForceLaw::InversePower {
+                exponent,
+                reference,
+            } => -(reference / distance).powf(exponent - 2.) / ((exponent - 1.) * distance),
+        }
+    }
+
+    /// Same law with its parameters brought back in range, scene files aren't checked
+    /// otherwise. A zero range or an exponent of 1 would divide by zero.
+    pub fn validated(self) -> Self {
+        let length = |value: f32| {
+            if value.is_finite() {
+                value.max(MIN_LENGTH)
+            } else {
+                MIN_LENGTH
+            }
+        };
+        let law = match self {
+            ForceLaw::Newtonian => ForceLaw::Newtonian,
+            ForceLaw::Softened { length: value } => ForceLaw::Softened {
+                length: length(value),
+            },
+            ForceLaw::Yukawa { range } => ForceLaw::Yukawa {
+                range: length(range),
+            },
+            ForceLaw::InversePower {
+                exponent,
+                reference,
+            } => ForceLaw::InversePower {
+                exponent: if exponent.is_finite() {
+                    exponent.clamp(MIN_EXPONENT, MAX_EXPONENT)
+                } else {
+                    2.
+                },
+                reference: length(reference),
+            },
+        };
+
+        if law != self {
+            warn!("Force law {self:?} out of range, using {law:?}");
+        }
+        law
+    }
+
+    /// Following law, with its default parameters, to cycle through them in the menu
+    pub fn next(self) -> Self {
+        match self {
+            ForceLaw::Newtonian => ForceLaw::Softened { length: 10_000. },
+            ForceLaw::Softened { .. } => ForceLaw::Yukawa { range: 1_000_000. },
+            ForceLaw::Yukawa { .. } => ForceLaw::InversePower {
+                exponent: 2.5,
+                reference: 100_000.,
+            },
+            ForceLaw::InversePower { .. } => ForceLaw::Newtonian,
+        }
+    }
+}
+
+impl fmt::Display for ForceLaw {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ForceLaw::Newtonian => write!(f, "Newtonian"),
+            ForceLaw::Softened { length } => write!(f, "Softened {length:.0} km"),
+            ForceLaw::Yukawa { range } => write!(f, "Yukawa {range:.0} km"),
+            ForceLaw::InversePower { exponent, .. } => write!(f, "Inverse power {exponent:.2}"),
+        }
+    }
+}
+
+/// Gravity of a scene, adjustable at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Gravity {
+    /// km³ / (kg s²)
+    pub constant: f32,
+    pub law: ForceLaw,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self {
+            constant: GRAVITY_CONSTANT,
+            law: ForceLaw::Newtonian,
+        }
+    }
+}
+
+impl Gravity {
+    /// Acceleration (km/s²) at `position` caused by a body of `mass` (kg) at `source`, in km
+    pub fn acceleration(&self, position: Vec3, source: Vec3, mass: f32) -> Vec3 {
+        let delta = source - position;
+        let distance = delta.length();
+        if distance <= f32::EPSILON {
+            return Vec3::ZERO;
+        }
+
+        delta / distance * (self.constant * mass * self.law.strength(distance))
+    }
+
+    /// Speed (km/s) of a circular orbit `distance` km from a body of `mass` kg
+    pub fn circular_speed(&self, mass: f32, distance: f32) -> f32 {
+        (self.constant * mass * distance * self.law.strength(distance)).sqrt()
+    }
+
+    /// Gravitational potential (km²/s²) at `position` of a body of `mass` (kg) at `source`.
+    /// Flat inside the body's `radius` so it stays finite.
+    pub fn potential(&self, position: Vec3, source: Vec3, mass: f32, radius: f32) -> f32 {
+        let distance = position.distance(source).max(radius);
+        self.constant * mass * self.law.potential(distance)
+    }
+}
+
+impl fmt::Display for Gravity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, G = {:.3e}", self.law, self.constant)
+    }
+}
+
+/// Electrostatic acceleration (km/s²) of a body of `mass` (kg) and `charge` (C) at `position`,
+/// caused by a `source_charge` at `source`. Like charges repel.
+pub fn coulomb_acceleration(
+    position: Vec3,
+    charge: f32,
+    mass: f32,
+    source: Vec3,
+    source_charge: f32,
+) -> Vec3 {
+    let delta = position - source;
+    let distance_sq = delta.length_squared();
+    if distance_sq <= f32::EPSILON || charge == 0. || source_charge == 0. {
+        return Vec3::ZERO;
+    }
+
+    // Divided by the mass first, the product of two large charges overflows f32
+    delta.normalize() * (COULOMB_CONSTANT * charge / mass * source_charge / distance_sq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAWS: [ForceLaw; 4] = [
+        ForceLaw::Newtonian,
+        ForceLaw::Softened { length: 10_000. },
+        ForceLaw::Yukawa { range: 50_000. },
+        ForceLaw::InversePower {
+            exponent: 2.5,
+            reference: 20_000.,
+        },
+    ];
+
+    fn assert_relative(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() <= tolerance * expected.abs(),
+            "{value} != {expected}"
+        );
+    }
+
+    #[test]
+    fn strength_is_the_slope_of_the_potential() {
+        for law in LAWS {
+            for distance in [2_000., 10_000., 30_000., 100_000.] {
+                // Wide enough for f32 where the softened potential is almost flat
+                let step = distance * 1e-2;
+                let slope =
+                    (law.potential(distance + step) - law.potential(distance - step)) / (2. * step);
+                assert_relative(law.strength(distance), slope, 2e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn potentials_vanish_far_away() {
+        for law in LAWS {
+            let near = law.potential(1_000.);
+            let far = law.potential(1e9);
+            assert!(near < far && far <= 0., "{law:?}");
+            assert!(far.abs() < 1e-3 * near.abs(), "{law:?}");
+        }
+    }
+
+    #[test]
+    fn laws_match_the_inverse_square_where_they_should() {
+        let newtonian = |distance: f32| ForceLaw::Newtonian.strength(distance);
+
+        assert_relative(newtonian(1_000.), 1e-6, 1e-6);
+        // Softening only matters inside its length
+        let softened = ForceLaw::Softened { length: 10. };
+        assert_relative(softened.strength(100_000.), newtonian(100_000.), 1e-6);
+        assert!(softened.strength(0.).is_finite());
+        // Screening only matters beyond its range
+        let yukawa = ForceLaw::Yukawa { range: 1e6 };
+        assert_relative(yukawa.strength(100.), newtonian(100.), 1e-6);
+        assert!(yukawa.strength(1e7) < 1e-3 * newtonian(1e7));
+        // The power law crosses the inverse square at its reference
+        let power = ForceLaw::InversePower {
+            exponent: 3.,
+            reference: 5_000.,
+        };
+        assert_relative(power.strength(5_000.), newtonian(5_000.), 1e-6);
+        assert!(power.strength(50_000.) < newtonian(50_000.));
+    }
+
+    #[test]
+    fn valid_laws_are_kept() {
+        for law in LAWS {
+            assert_eq!(law.validated(), law);
+        }
+    }
+
+    #[test]
+    fn invalid_laws_are_brought_in_range() {
+        assert_eq!(
+            ForceLaw::Yukawa { range: 0. }.validated(),
+            ForceLaw::Yukawa { range: MIN_LENGTH }
+        );
+        assert_eq!(
+            ForceLaw::Softened { length: f32::NAN }.validated(),
+            ForceLaw::Softened { length: MIN_LENGTH }
+        );
+        assert_eq!(
+            ForceLaw::InversePower {
+                exponent: 1.,
+                reference: -5.,
+            }
+            .validated(),
+            ForceLaw::InversePower {
+                exponent: MIN_EXPONENT,
+                reference: MIN_LENGTH,
+            }
+        );
+        assert_eq!(
+            ForceLaw::InversePower {
+                exponent: 10.,
+                reference: 100.,
+            }
+            .validated(),
+            ForceLaw::InversePower {
+                exponent: MAX_EXPONENT,
+                reference: 100.,
+            }
+        );
+        assert_eq!(
+            ForceLaw::InversePower {
+                exponent: f32::INFINITY,
+                reference: 100.,
+            }
+            .validated(),
+            ForceLaw::InversePower {
+                exponent: 2.,
+                reference: 100.,
+            }
+        );
+    }
+
+    #[test]
+    fn circular_speed_balances_the_pull() {
+        // Moon around the Earth, about 1.02 km/s
+        let earth = 5.972e24;
+        let speed = Gravity::default().circular_speed(earth, 384_400.);
+        assert_relative(speed, 1.018, 1e-2);
+
+        for law in LAWS {
+            let gravity = Gravity {
+                law,
+                ..Gravity::default()
+            };
+            for distance in [5_000., 40_000., 200_000.] {
+                let pull = gravity
+                    .acceleration(Vec3::new(distance, 0., 0.), Vec3::ZERO, earth)
+                    .length();
+                let speed = gravity.circular_speed(earth, distance);
+                assert_relative(speed * speed / distance, pull, 1e-4);
+            }
+        }
+    }
+}
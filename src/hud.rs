@@ -1,10 +1,22 @@
 use bevy::{
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    input::mouse::{MouseScrollUnit, MouseWheel},
     prelude::*,
+    ui::RelativeCursorPosition,
     window::PrimaryWindow,
 };
 
-use crate::GameSpeed;
+use crate::{
+    common::Settings,
+    events::EventLog,
+    forces::Gravity,
+    input::{Action, ActionState},
+    maneuver::ManeuverPlanner,
+    particles::TestParticles,
+    relativity::Precession,
+    ship::Ship,
+    GameSpeed, PhysicsSettings, Planet, SimStats, INTEGRATOR_NAME, TIME_SPEED,
+};
 
 pub struct HUDPlugin;
 
@@ -14,13 +26,64 @@ struct FpsText;
 #[derive(Component)]
 struct SpeedText;
 
+#[derive(Component)]
+struct ClockText;
+
+#[derive(Component)]
+struct BodiesText;
+
+#[derive(Component)]
+struct PhysicsText;
+
+/// Fuel and delta-v of the player ship, only shown while flying one, see `HudPanel::Ship`
+#[derive(Component)]
+struct ShipText;
+
+/// Latest body events, scrolled with the mouse wheel while the cursor is over it
+#[derive(Component)]
+struct EventLogText;
+
+/// Log entries shown at once
+const EVENT_LOG_LINES: usize = 8;
+
+/// The cursor is over the event log, the mouse wheel scrolls it instead of zooming
+#[derive(Resource, Default)]
+pub struct CursorOverHud(pub bool);
+
 #[derive(Component)]
 struct Crosshair;
 
+/// Text panel that can be shown or hidden from the settings
+#[derive(Component, Clone, Copy)]
+enum HudPanel {
+    Fps,
+    Speed,
+    Clock,
+    Bodies,
+    Physics,
+    EventLog,
+    Ship,
+}
+
+impl HudPanel {
+    fn shown(self, settings: &Settings) -> bool {
+        match self {
+            HudPanel::Fps => settings.hud.show_fps,
+            HudPanel::Speed => settings.hud.show_speed,
+            HudPanel::Clock => settings.hud.show_clock,
+            HudPanel::Bodies => settings.hud.show_bodies,
+            HudPanel::Physics => settings.hud.show_physics,
+            HudPanel::EventLog => settings.hud.show_event_log,
+            HudPanel::Ship => settings.hud.show_ship,
+        }
+    }
+}
+
 impl Plugin for HUDPlugin {
     fn build(&self, app: &mut App) {
         app //
             .add_plugins(FrameTimeDiagnosticsPlugin)
+            .init_resource::<CursorOverHud>()
             .add_systems(Startup, setup_hud)
             .add_systems(
                 Update,
@@ -28,6 +91,13 @@ impl Plugin for HUDPlugin {
                     crosshair_visibility,
                     fps_text_update_system,
                     speed_text_update_system,
+                    clock_text_update_system,
+                    bodies_text_update_system,
+                    physics_text_update_system,
+                    ship_text_update_system.after(panels_visibility),
+                    event_log_update_system,
+                    toggle_panels,
+                    panels_visibility.run_if(resource_changed::<Settings>),
                 ),
             );
     }
@@ -111,6 +181,7 @@ fn setup_hud(mut commands: Commands) {
             }),
         ]),
         FpsText,
+        HudPanel::Fps,
     ));
 
     // SpeedText
@@ -136,7 +207,95 @@ fn setup_hud(mut commands: Commands) {
             ..default()
         }),
         SpeedText,
+        HudPanel::Speed,
+    ));
+
+    // Event log, above the speed
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: Color::SILVER,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(35.0),
+            left: Val::Px(5.0),
+            max_width: Val::Percent(50.0),
+            ..default()
+        }),
+        RelativeCursorPosition::default(),
+        EventLogText,
+        HudPanel::EventLog,
     ));
+
+    // Simulation stats, stacked on the top right
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                right: Val::Px(5.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::FlexEnd,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((stat_text("TIME: ", Color::CYAN), ClockText, HudPanel::Clock));
+            parent.spawn((
+                stat_text("BODIES: ", Color::CYAN),
+                BodiesText,
+                HudPanel::Bodies,
+            ));
+            parent.spawn((
+                stat_text("PHYSICS: ", Color::CYAN),
+                PhysicsText,
+                HudPanel::Physics,
+            ));
+            parent.spawn((stat_text("SHIP: ", Color::ORANGE), ShipText, HudPanel::Ship));
+        });
+}
+
+fn stat_text(label: &str, color: Color) -> TextBundle {
+    TextBundle::from_sections([
+        TextSection::new(
+            label,
+            TextStyle {
+                font_size: 20.0,
+                ..default()
+            },
+        ),
+        TextSection::from_style(TextStyle {
+            font_size: 20.0,
+            color,
+            ..default()
+        }),
+    ])
+}
+
+/// Simulated seconds in the largest unit that keeps the number readable
+fn format_duration(seconds: f64) -> String {
+    const MINUTE: f64 = 60.;
+    const HOUR: f64 = 60. * MINUTE;
+    const DAY: f64 = 24. * HOUR;
+    const YEAR: f64 = 365.25 * DAY;
+
+    if seconds < MINUTE {
+        format!("{seconds:.1} s")
+    } else if seconds < HOUR {
+        format!("{:.1} min", seconds / MINUTE)
+    } else if seconds < DAY {
+        format!("{:.1} h", seconds / HOUR)
+    } else if seconds < YEAR {
+        format!("{:.2} days", seconds / DAY)
+    } else {
+        format!("{:.2} years", seconds / YEAR)
+    }
 }
 
 fn crosshair_visibility(
@@ -178,3 +337,197 @@ fn speed_text_update_system(
         text.sections[1].value = format!("x{:.2}", game_speed.speed);
     }
 }
+
+fn clock_text_update_system(
+    mut query: Query<&mut Text, With<ClockText>>,
+    stats: Res<SimStats>,
+    game_speed: Res<GameSpeed>,
+) {
+    for mut text in &mut query {
+        text.sections[1].value = format!(
+            "{} (1 s = {})",
+            format_duration(stats.elapsed),
+            format_duration(f64::from(TIME_SPEED * game_speed.speed))
+        );
+    }
+}
+
+fn bodies_text_update_system(
+    mut query: Query<&mut Text, With<BodiesText>>,
+    stats: Res<SimStats>,
+    planets_query: Query<(), With<Planet>>,
+    particles: Res<TestParticles>,
+) {
+    for mut text in &mut query {
+        text.sections[1].value = format!(
+            "{} | COLLISIONS: {}",
+            planets_query.iter().count(),
+            stats.collisions
+        );
+        if !particles.0.is_empty() {
+            text.sections[1].value += &format!(" | PARTICLES: {}", particles.0.len());
+        }
+    }
+}
+
+fn physics_text_update_system(
+    mut query: Query<&mut Text, With<PhysicsText>>,
+    stats: Res<SimStats>,
+    physics: Res<PhysicsSettings>,
+    precession: Res<Precession>,
+) {
+    for mut text in &mut query {
+        text.sections[1].value = format!(
+            "{INTEGRATOR_NAME} | {} steps/frame | {:.3} ms/step",
+            stats.substeps,
+            stats.tick_duration * 1000.
+        );
+        if physics.gravity != Gravity::default() {
+            text.sections[1].value += &format!(" | {}", physics.gravity);
+        }
+        if let Some(medium) = physics.medium {
+            text.sections[1].value += &format!(" | {medium}");
+        }
+        if physics.post_newtonian {
+            text.sections[1].value += &format!(" | 1PN c = {} km/s", physics.speed_of_light);
+            if let Some(measured) = precession.measured() {
+                text.sections[1].value += &format!(
+                    "\n{} periapsis: {:.3}°/orbit, predicted {:.3}°/orbit",
+                    precession.name,
+                    measured.to_degrees(),
+                    precession.predicted.to_degrees()
+                );
+            }
+        }
+    }
+}
+
+/// Also hides the panel without a ship, after `panels_visibility` shows it
+fn ship_text_update_system(
+    mut query: Query<(&mut Text, &mut Visibility), With<ShipText>>,
+    ship_q: Query<&Ship>,
+    settings: Res<Settings>,
+    planner: Res<ManeuverPlanner>,
+    stats: Res<SimStats>,
+) {
+    let ship = ship_q
+        .get_single()
+        .ok()
+        .filter(|_| HudPanel::Ship.shown(&settings));
+
+    for (mut text, mut visibility) in &mut query {
+        let Some(ship) = ship else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        *visibility = Visibility::Inherited;
+        text.sections[1].value = format!(
+            "FUEL: {:.0} kg ({:.0}%) | ΔV: {:.3} km/s",
+            ship.fuel,
+            ship.fuel_fraction() * 100.,
+            ship.delta_v()
+        );
+        if let Some(node) = planner.node {
+            text.sections[1].value += &format!(
+                "\nNODE: in {} | BURN: {:.3} km/s",
+                format_duration(node.time - stats.elapsed),
+                node.delta_v.length()
+            );
+        }
+    }
+}
+
+/// Shows the newest entries, or older ones after scrolling up over the log
+fn event_log_update_system(
+    log: Res<EventLog>,
+    settings: Res<Settings>,
+    mut scroll: Local<usize>,
+    mut wheel: EventReader<MouseWheel>,
+    mut cursor_over_hud: ResMut<CursorOverHud>,
+    mut query: Query<(&mut Text, &RelativeCursorPosition), With<EventLogText>>,
+) {
+    let Ok((mut text, cursor)) = query.get_single_mut() else {
+        return;
+    };
+
+    // Hidden nodes keep their last cursor position
+    let over = HudPanel::EventLog.shown(&settings) && cursor.mouse_over();
+    if cursor_over_hud.0 != over {
+        cursor_over_hud.0 = over;
+    }
+
+    let lines: f32 = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 20.,
+        })
+        .sum();
+    let max_scroll = log.entries.len().saturating_sub(EVENT_LOG_LINES);
+    let new_scroll = if over && lines != 0. {
+        (*scroll as f32 + lines)
+            .round()
+            .clamp(0., max_scroll as f32) as usize
+    } else {
+        (*scroll).min(max_scroll)
+    };
+
+    if new_scroll == *scroll && !log.is_changed() {
+        return;
+    }
+    *scroll = new_scroll;
+
+    let end = log.entries.len() - *scroll;
+    let start = end.saturating_sub(EVENT_LOG_LINES);
+    text.sections[0].value = log
+        .entries
+        .range(start..end)
+        .map(|entry| format!("[{}] {}", format_duration(entry.time), entry.message))
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+fn toggle_panels(actions: Res<ActionState>, mut settings: ResMut<Settings>) {
+    if actions.just_pressed(Action::ToggleFpsPanel) {
+        settings.hud.show_fps = !settings.hud.show_fps;
+    }
+
+    if actions.just_pressed(Action::ToggleSpeedPanel) {
+        settings.hud.show_speed = !settings.hud.show_speed;
+    }
+
+    if actions.just_pressed(Action::ToggleClockPanel) {
+        settings.hud.show_clock = !settings.hud.show_clock;
+    }
+
+    if actions.just_pressed(Action::ToggleBodiesPanel) {
+        settings.hud.show_bodies = !settings.hud.show_bodies;
+    }
+
+    if actions.just_pressed(Action::TogglePhysicsPanel) {
+        settings.hud.show_physics = !settings.hud.show_physics;
+    }
+
+    if actions.just_pressed(Action::ToggleShipPanel) {
+        settings.hud.show_ship = !settings.hud.show_ship;
+    }
+
+    if actions.just_pressed(Action::ToggleLabels) {
+        settings.hud.show_labels = !settings.hud.show_labels;
+    }
+
+    if actions.just_pressed(Action::ToggleEventLog) {
+        settings.hud.show_event_log = !settings.hud.show_event_log;
+    }
+}
+
+fn panels_visibility(settings: Res<Settings>, mut panels_q: Query<(&mut Visibility, &HudPanel)>) {
+    for (mut visibility, panel) in &mut panels_q {
+        *visibility = if panel.shown(&settings) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
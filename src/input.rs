@@ -0,0 +1,491 @@
+use std::fs;
+
+use bevy::{
+    ecs::system::SystemParam,
+    input::InputSystem,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::common::config_path;
+
+const CONTROLS_FILE: &str = "controls.ron";
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .insert_resource(load_input_map())
+            .init_resource::<ActionState>()
+            .add_systems(PreUpdate, update_action_state.after(InputSystem));
+    }
+}
+
+/// Everything the player can do, independent of the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    RollLeft,
+    RollRight,
+    Boost,
+    LookLeft,
+    LookRight,
+    LookUp,
+    LookDown,
+    OrbitDrag,
+    ZoomIn,
+    ZoomOut,
+    ToggleCameraMode,
+    ToggleShip,
+    ManeuverNode,
+    NodeEarlier,
+    NodeLater,
+    NodePrograde,
+    NodeRetrograde,
+    NodeNormal,
+    NodeAntiNormal,
+    NodeRadialOut,
+    NodeRadialIn,
+    CycleTarget,
+    ToggleCursorGrab,
+    SlowDown,
+    SpeedUp,
+    ResetSpeed,
+    TogglePause,
+    SpawnPlanet,
+    ToggleFullscreen,
+    ToggleLogDistance,
+    BodyScaleUp,
+    BodyScaleDown,
+    ToggleFpsPanel,
+    ToggleSpeedPanel,
+    ToggleClockPanel,
+    ToggleBodiesPanel,
+    TogglePhysicsPanel,
+    ToggleShipPanel,
+    ToggleLabels,
+    ToggleEventLog,
+    ToggleMinimap,
+    MinimapClick,
+    ToggleFieldGrid,
+    ToggleFieldArrows,
+    ToggleBodyVectors,
+    ToggleForceLines,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    /// Second key pressed while holding the first one
+    Chord(KeyCode, KeyCode),
+    Mouse(MouseButton),
+    /// Analog buttons like the triggers report how far they are pressed
+    GamepadButton(GamepadButtonType),
+    /// Stick pushed past the dead zone in one direction
+    GamepadAxis(GamepadAxisType, AxisDirection),
+}
+
+impl Binding {
+    /// Whether pressing one of the bindings also triggers the other
+    fn overlaps(&self, other: &Binding) -> bool {
+        match (self, other) {
+            // Holding either key of the chord presses the lone key too
+            (Binding::Key(key), Binding::Chord(modifier, chord_key))
+            | (Binding::Chord(modifier, chord_key), Binding::Key(key)) => {
+                key == modifier || key == chord_key
+            }
+            _ => self == other,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct InputMap {
+    pub dead_zone: f32,
+    pub bindings: Vec<(Action, Vec<Binding>)>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use AxisDirection::*;
+        use Binding::*;
+        use GamepadAxisType::*;
+        use GamepadButtonType as Button;
+
+        InputMap {
+            dead_zone: 0.15,
+            bindings: vec![
+                (
+                    Action::MoveForward,
+                    vec![Key(KeyCode::KeyW), GamepadAxis(LeftStickY, Positive)],
+                ),
+                (
+                    Action::MoveBack,
+                    vec![Key(KeyCode::KeyS), GamepadAxis(LeftStickY, Negative)],
+                ),
+                (
+                    Action::MoveLeft,
+                    vec![Key(KeyCode::KeyA), GamepadAxis(LeftStickX, Negative)],
+                ),
+                (
+                    Action::MoveRight,
+                    vec![Key(KeyCode::KeyD), GamepadAxis(LeftStickX, Positive)],
+                ),
+                (
+                    Action::MoveUp,
+                    vec![Key(KeyCode::Space), GamepadButton(Button::South)],
+                ),
+                (
+                    Action::MoveDown,
+                    vec![Key(KeyCode::KeyC), GamepadButton(Button::East)],
+                ),
+                (
+                    Action::RollLeft,
+                    vec![Key(KeyCode::KeyQ), GamepadButton(Button::LeftTrigger)],
+                ),
+                (
+                    Action::RollRight,
+                    vec![Key(KeyCode::KeyE), GamepadButton(Button::RightTrigger)],
+                ),
+                (
+                    Action::Boost,
+                    vec![Key(KeyCode::ShiftLeft), GamepadButton(Button::LeftThumb)],
+                ),
+                (Action::LookLeft, vec![GamepadAxis(RightStickX, Negative)]),
+                (Action::LookRight, vec![GamepadAxis(RightStickX, Positive)]),
+                (Action::LookUp, vec![GamepadAxis(RightStickY, Positive)]),
+                (Action::LookDown, vec![GamepadAxis(RightStickY, Negative)]),
+                (Action::OrbitDrag, vec![Mouse(MouseButton::Middle)]),
+                (Action::ZoomIn, vec![GamepadButton(Button::DPadUp)]),
+                (Action::ZoomOut, vec![GamepadButton(Button::DPadDown)]),
+                (
+                    Action::ToggleCameraMode,
+                    vec![Key(KeyCode::KeyV), GamepadButton(Button::Select)],
+                ),
+                (
+                    Action::ToggleShip,
+                    vec![Key(KeyCode::KeyF), GamepadButton(Button::West)],
+                ),
+                (Action::ManeuverNode, vec![Key(KeyCode::KeyN)]),
+                (Action::NodeEarlier, vec![Key(KeyCode::Comma)]),
+                (Action::NodeLater, vec![Key(KeyCode::Period)]),
+                (Action::NodePrograde, vec![Key(KeyCode::KeyI)]),
+                (Action::NodeRetrograde, vec![Key(KeyCode::KeyK)]),
+                (Action::NodeNormal, vec![Key(KeyCode::KeyU)]),
+                (Action::NodeAntiNormal, vec![Key(KeyCode::KeyO)]),
+                (Action::NodeRadialOut, vec![Key(KeyCode::KeyY)]),
+                (Action::NodeRadialIn, vec![Key(KeyCode::KeyH)]),
+                (
+                    Action::CycleTarget,
+                    vec![Key(KeyCode::Tab), GamepadButton(Button::DPadRight)],
+                ),
+                (Action::ToggleCursorGrab, vec![Mouse(MouseButton::Right)]),
+                (
+                    Action::SlowDown,
+                    vec![Key(KeyCode::KeyZ), GamepadButton(Button::LeftTrigger2)],
+                ),
+                (
+                    Action::SpeedUp,
+                    vec![Key(KeyCode::KeyX), GamepadButton(Button::RightTrigger2)],
+                ),
+                (
+                    Action::ResetSpeed,
+                    vec![Key(KeyCode::KeyR), GamepadButton(Button::North)],
+                ),
+                (
+                    Action::TogglePause,
+                    vec![
+                        Key(KeyCode::Escape),
+                        Key(KeyCode::KeyP),
+                        GamepadButton(Button::Start),
+                    ],
+                ),
+                (Action::SpawnPlanet, vec![Key(KeyCode::KeyG)]),
+                (
+                    Action::ToggleFullscreen,
+                    vec![Key(KeyCode::F11), Chord(KeyCode::AltLeft, KeyCode::Enter)],
+                ),
+                (Action::ToggleLogDistance, vec![Key(KeyCode::KeyL)]),
+                (Action::BodyScaleUp, vec![Key(KeyCode::BracketRight)]),
+                (Action::BodyScaleDown, vec![Key(KeyCode::BracketLeft)]),
+                (Action::ToggleFpsPanel, vec![Key(KeyCode::F1)]),
+                (Action::ToggleSpeedPanel, vec![Key(KeyCode::F2)]),
+                (Action::ToggleClockPanel, vec![Key(KeyCode::F3)]),
+                (Action::ToggleBodiesPanel, vec![Key(KeyCode::F4)]),
+                (Action::TogglePhysicsPanel, vec![Key(KeyCode::F5)]),
+                // The function keys are all taken
+                (Action::ToggleShipPanel, vec![Key(KeyCode::KeyT)]),
+                (Action::ToggleLabels, vec![Key(KeyCode::F6)]),
+                (Action::ToggleEventLog, vec![Key(KeyCode::F12)]),
+                (Action::ToggleMinimap, vec![Key(KeyCode::KeyM)]),
+                (Action::MinimapClick, vec![Mouse(MouseButton::Left)]),
+                (Action::ToggleFieldGrid, vec![Key(KeyCode::F7)]),
+                (Action::ToggleFieldArrows, vec![Key(KeyCode::F8)]),
+                (Action::ToggleBodyVectors, vec![Key(KeyCode::F9)]),
+                (Action::ToggleForceLines, vec![Key(KeyCode::F10)]),
+            ],
+        }
+    }
+}
+
+impl InputMap {
+    /// Removes bindings already used by a previous action, so one input never does two things.
+    /// Returns a description of every removed binding.
+    fn resolve_conflicts(&mut self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        let mut used: Vec<(Action, Binding)> = Vec::new();
+
+        for (action, bindings) in &mut self.bindings {
+            bindings.retain(|binding| {
+                match used.iter().find(|(_, other)| binding.overlaps(other)) {
+                    Some((other_action, _)) if other_action != action => {
+                        conflicts.push(format!(
+                            "{binding:?} is bound to both {other_action:?} and {action:?}, keeping {other_action:?}"
+                        ));
+                        false
+                    }
+                    _ => {
+                        used.push((*action, *binding));
+                        true
+                    }
+                }
+            });
+        }
+
+        conflicts
+    }
+
+    /// Adds the default bindings of actions missing from the file
+    fn fill_missing(&mut self) {
+        for (action, bindings) in InputMap::default().bindings {
+            if !self.bindings.iter().any(|(other, _)| *other == action) {
+                self.bindings.push((action, bindings));
+            }
+        }
+    }
+}
+
+fn load_input_map() -> InputMap {
+    let Some(path) = config_path(CONTROLS_FILE) else {
+        return InputMap::default();
+    };
+
+    let mut input_map = match fs::read_to_string(&path) {
+        Ok(contents) => match ron::from_str::<InputMap>(&contents) {
+            Ok(input_map) => input_map,
+            Err(err) => {
+                warn!("Invalid {}, using default controls: {err}", path.display());
+                return InputMap::default();
+            }
+        },
+        Err(_) => {
+            // First launch, write the defaults so they can be edited
+            let input_map = InputMap::default();
+            let pretty = ron::ser::PrettyConfig::default();
+            if let Ok(contents) = ron::ser::to_string_pretty(&input_map, pretty) {
+                if let Err(err) = fs::write(&path, contents) {
+                    warn!("Could not write {}: {err}", path.display());
+                }
+            }
+            input_map
+        }
+    };
+
+    input_map.fill_missing();
+    for conflict in input_map.resolve_conflicts() {
+        warn!("Controls conflict: {conflict}");
+    }
+
+    input_map
+}
+
+/// Current value of every action, 0 is released and 1 fully pressed
+#[derive(Resource, Default)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    previous: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) > 0.
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && !self.previous.contains(&action)
+    }
+
+    /// `positive` minus `negative`, for movement along one axis
+    pub fn axis(&self, negative: Action, positive: Action) -> f32 {
+        self.value(positive) - self.value(negative)
+    }
+}
+
+#[derive(SystemParam)]
+struct GamepadInput<'w> {
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+    button_axes: Res<'w, Axis<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+fn update_action_state(
+    input_map: Res<InputMap>,
+    mut action_state: ResMut<ActionState>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    gamepad: GamepadInput,
+) {
+    let binding_value = |binding: &Binding| -> f32 {
+        let value = match *binding {
+            Binding::Key(key) => f32::from(u8::from(keyboard_input.pressed(key))),
+            Binding::Chord(modifier, key) => f32::from(u8::from(
+                keyboard_input.pressed(modifier) && keyboard_input.pressed(key),
+            )),
+            Binding::Mouse(button) => f32::from(u8::from(mouse_buttons.pressed(button))),
+            Binding::GamepadButton(button_type) => gamepad
+                .gamepads
+                .iter()
+                .map(|pad| {
+                    let button = GamepadButton::new(pad, button_type);
+                    gamepad
+                        .button_axes
+                        .get(button)
+                        .unwrap_or_else(|| f32::from(u8::from(gamepad.buttons.pressed(button))))
+                })
+                .fold(0., f32::max),
+            Binding::GamepadAxis(axis_type, direction) => gamepad
+                .gamepads
+                .iter()
+                .filter_map(|pad| gamepad.axes.get(GamepadAxis::new(pad, axis_type)))
+                .map(|value| match direction {
+                    AxisDirection::Positive => value,
+                    AxisDirection::Negative => -value,
+                })
+                .fold(0., f32::max),
+        };
+
+        if value > input_map.dead_zone {
+            value.min(1.)
+        } else {
+            0.
+        }
+    };
+
+    let action_state = &mut *action_state;
+    action_state.previous = action_state
+        .values
+        .iter()
+        .filter(|(_, &value)| value > 0.)
+        .map(|(&action, _)| action)
+        .collect();
+
+    action_state.values.clear();
+    for (action, bindings) in &input_map.bindings {
+        let value = bindings.iter().map(binding_value).fold(0., f32::max);
+        if value > 0. {
+            action_state.values.insert(*action, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords_overlap_both_of_their_keys() {
+        let chord = Binding::Chord(KeyCode::AltLeft, KeyCode::Enter);
+
+        assert!(chord.overlaps(&Binding::Key(KeyCode::AltLeft)));
+        assert!(chord.overlaps(&Binding::Key(KeyCode::Enter)));
+        assert!(Binding::Key(KeyCode::Enter).overlaps(&chord));
+        assert!(!chord.overlaps(&Binding::Key(KeyCode::KeyA)));
+        assert!(!chord.overlaps(&Binding::Chord(KeyCode::AltLeft, KeyCode::KeyA)));
+        assert!(chord.overlaps(&chord));
+        assert!(!Binding::Key(KeyCode::KeyA).overlaps(&Binding::Mouse(MouseButton::Left)));
+    }
+
+    #[test]
+    fn defaults_have_no_conflicts() {
+        assert!(InputMap::default().resolve_conflicts().is_empty());
+    }
+
+    #[test]
+    fn conflicts_keep_the_first_action() {
+        let mut input_map = InputMap {
+            dead_zone: 0.15,
+            bindings: vec![
+                (Action::MoveUp, vec![Binding::Key(KeyCode::Space)]),
+                (
+                    Action::TogglePause,
+                    vec![Binding::Key(KeyCode::Space), Binding::Key(KeyCode::KeyP)],
+                ),
+                (
+                    Action::ToggleFullscreen,
+                    vec![Binding::Chord(KeyCode::ShiftLeft, KeyCode::Space)],
+                ),
+            ],
+        };
+
+        assert_eq!(input_map.resolve_conflicts().len(), 2);
+        assert_eq!(
+            input_map.bindings,
+            vec![
+                (Action::MoveUp, vec![Binding::Key(KeyCode::Space)]),
+                (Action::TogglePause, vec![Binding::Key(KeyCode::KeyP)]),
+                (Action::ToggleFullscreen, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_action_can_repeat_a_binding() {
+        let mut input_map = InputMap {
+            dead_zone: 0.15,
+            bindings: vec![(
+                Action::MoveUp,
+                vec![Binding::Key(KeyCode::Space), Binding::Key(KeyCode::Space)],
+            )],
+        };
+
+        assert!(input_map.resolve_conflicts().is_empty());
+        assert_eq!(input_map.bindings[0].1.len(), 2);
+    }
+
+    #[test]
+    fn fill_missing_keeps_custom_bindings() {
+        let mut input_map = InputMap {
+            dead_zone: 0.15,
+            bindings: vec![(Action::MoveUp, vec![Binding::Key(KeyCode::KeyJ)])],
+        };
+        input_map.fill_missing();
+
+        let defaults = InputMap::default();
+        assert_eq!(input_map.bindings.len(), defaults.bindings.len());
+        assert_eq!(
+            input_map.bindings[0],
+            (Action::MoveUp, vec![Binding::Key(KeyCode::KeyJ)])
+        );
+        for (action, _) in &defaults.bindings {
+            assert!(input_map.bindings.iter().any(|(other, _)| other == action));
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use bevy::{prelude::*, ui::UiSystem, utils::HashSet};
+
+use crate::{
+    common::Settings,
+    player::{MainCamera, SelectedBody},
+    view::ViewScale,
+    Planet,
+};
+
+// World distance from the camera at which labels start to fade out, and are gone
+const LABEL_FADE_START: f32 = 2_000.;
+const LABEL_FADE_END: f32 = 10_000.;
+const LABEL_OFFSET: f32 = 12.; // pixels above the body center
+const EDGE_MARGIN: f32 = 24.; // pixels
+/// Bodies holding at least this fraction of the total mass always get a label
+const IMPORTANT_MASS_FRACTION: f32 = 0.1;
+/// Labels on screen at once, the important bodies get one first and then the closest ones.
+/// Text layout is the slow part of the UI, one label per body stalls with thousands of them.
+const MAX_LABELS: usize = 64;
+
+pub struct LabelsPlugin;
+
+impl Plugin for LabelsPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .add_systems(Startup, spawn_labels)
+            .add_systems(
+                PostUpdate,
+                (assign_labels, update_labels)
+                    .chain()
+                    .before(UiSystem::Layout),
+            );
+    }
+}
+
+/// Screen-space label following a body, `Entity::PLACEHOLDER` while unused
+#[derive(Component)]
+struct BodyLabel(Entity);
+
+fn spawn_labels(mut commands: Commands) {
+    for _ in 0..MAX_LABELS {
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::from_style(TextStyle {
+                    font_size: 18.0,
+                    ..default()
+                }),
+                TextSection::from_style(TextStyle {
+                    font_size: 14.0,
+                    color: Color::GRAY,
+                    ..default()
+                }),
+            ])
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            }),
+            BodyLabel(Entity::PLACEHOLDER),
+        ));
+    }
+}
+
+/// Hands the labels to the important bodies, then to the ones closest to the camera.
+/// A label keeps its body while it stays among them, so they don't jump around.
+fn assign_labels(
+    view_scale: Res<ViewScale>,
+    selected: Res<SelectedBody>,
+    camera_q: Query<&Transform, With<MainCamera>>,
+    planets_q: Query<(Entity, &Planet)>,
+    mut labels_q: Query<&mut BodyLabel>,
+) {
+    let Ok(camera_transform) = camera_q.get_single() else {
+        return;
+    };
+    let total_mass: f32 = planets_q.iter().map(|(_, planet)| planet.mass()).sum();
+
+    // Important first, then by distance
+    let mut candidates: Vec<(bool, f32, Entity)> = planets_q
+        .iter()
+        .map(|(entity, planet)| {
+            let important =
+                selected.0 == Some(entity) || planet.mass() >= IMPORTANT_MASS_FRACTION * total_mass;
+            let distance = camera_transform
+                .translation
+                .distance(view_scale.to_world(planet.position));
+            (!important, distance, entity)
+        })
+        .collect();
+    if candidates.len() > MAX_LABELS {
+        candidates.select_nth_unstable_by(MAX_LABELS - 1, |a, b| {
+            a.0.cmp(&b.0).then(a.1.total_cmp(&b.1))
+        });
+        candidates.truncate(MAX_LABELS);
+    }
+
+    let wanted: HashSet<Entity> = candidates.iter().map(|&(.., entity)| entity).collect();
+    let mut unlabeled = wanted.clone();
+    for label in &labels_q {
+        unlabeled.remove(&label.0);
+    }
+    let mut unlabeled = unlabeled.into_iter();
+
+    for mut label in &mut labels_q {
+        if !wanted.contains(&label.0) {
+            label.0 = unlabeled.next().unwrap_or(Entity::PLACEHOLDER);
+        }
+    }
+}
+
+fn format_distance(km: f32) -> String {
+    if km < 1e6 {
+        format!("{km:.0} km")
+    } else {
+        format!("{:.2} M km", km / 1e6)
+    }
+}
+
+fn update_labels(
+    settings: Res<Settings>,
+    view_scale: Res<ViewScale>,
+    selected: Res<SelectedBody>,
+    camera_q: Query<(&Camera, &Transform), With<MainCamera>>,
+    planets_q: Query<(&Planet, Option<&Name>)>,
+    mut labels_q: Query<(&BodyLabel, &Node, &mut Style, &mut Text, &mut Visibility)>,
+) {
+    let Ok((camera, camera_transform)) = camera_q.get_single() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_size() else {
+        return;
+    };
+    // The camera has no parent and is moved in `Last`, so its `Transform` is what gets rendered
+    let camera_global = GlobalTransform::from(*camera_transform);
+    let camera_position = view_scale.to_physical(camera_transform.translation);
+    let total_mass: f32 = planets_q.iter().map(|(planet, _)| planet.mass()).sum();
+
+    for (label, node, mut style, mut text, mut visibility) in &mut labels_q {
+        let Ok((planet, name)) = planets_q.get(label.0) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        if !settings.hud.show_labels {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        let name = name.map_or("", Name::as_str);
+        let world_position = view_scale.to_world(planet.position);
+        let important =
+            selected.0 == Some(label.0) || planet.mass() >= IMPORTANT_MASS_FRACTION * total_mass;
+        let on_screen = camera
+            .world_to_viewport(&camera_global, world_position)
+            .filter(|position| position.cmpge(Vec2::ZERO).all() && position.cmple(viewport).all());
+
+        let (position, alpha) = if let Some(position) = on_screen {
+            let world_distance = camera_transform.translation.distance(world_position);
+            let fade = (world_distance - LABEL_FADE_START) / (LABEL_FADE_END - LABEL_FADE_START);
+            let alpha = if important {
+                1.
+            } else {
+                1. - fade.clamp(0., 1.)
+            };
+
+            text.sections[0].value = name.to_string();
+            (
+                position - Vec2::new(node.size().x / 2., node.size().y + LABEL_OFFSET),
+                alpha,
+            )
+        } else if important {
+            // Pin the label to the screen edge, in the direction of the body
+            let local = camera_transform
+                .compute_matrix()
+                .inverse()
+                .transform_point3(world_position);
+            let direction = Vec2::new(local.x, -local.y)
+                .try_normalize()
+                .unwrap_or(Vec2::Y);
+            let half_size = viewport / 2. - EDGE_MARGIN;
+            let t = (half_size.x / direction.x.abs()).min(half_size.y / direction.y.abs());
+            let edge = viewport / 2. + direction * t;
+
+            let arrow = if direction.x.abs() > direction.y.abs() {
+                if direction.x > 0. {
+                    ">"
+                } else {
+                    "<"
+                }
+            } else if direction.y > 0. {
+                "v"
+            } else {
+                "^"
+            };
+            text.sections[0].value = format!("{arrow} {name}");
+
+            let position = (edge - node.size() / 2.)
+                .min(viewport - node.size())
+                .max(Vec2::ZERO);
+            (position, 1.)
+        } else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        if alpha <= 0. {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        text.sections[1].value = format!(
+            "\n{} | {:.2} km/s",
+            format_distance(camera_position.distance(planet.position)),
+            planet.velocity.length()
+        );
+        text.sections[0].style.color = Color::WHITE.with_a(alpha);
+        text.sections[1].style.color = Color::GRAY.with_a(alpha);
+
+        style.left = Val::Px(position.x);
+        style.top = Val::Px(position.y);
+        *visibility = Visibility::Inherited;
+    }
+}
@@ -1,45 +1,144 @@
 // mod trail_plugin;
 mod common;
+mod drag;
+mod environment;
+mod events;
+mod field;
+mod forces;
 mod hud;
+mod input;
+mod labels;
+mod maneuver;
+mod menu;
+mod minimap;
+mod motion;
+mod octree;
+mod particles;
 mod player;
+mod relativity;
+mod scenes;
+mod ship;
+mod stars;
+mod surfaces;
+mod tides;
 mod utils;
-
-use std::f32::consts::PI;
-
-use bevy::prelude::*;
-use common::CommonPlugin;
+mod vectors;
+mod view;
+
+use std::{f32::consts::PI, time::Instant};
+
+use bevy::{
+    prelude::*,
+    tasks::{ComputeTaskPool, ParallelSlice},
+    utils::HashSet,
+};
+use common::{CommonPlugin, Settings};
+use drag::{DragPlugin, Medium};
+use environment::EnvironmentPlugin;
+use events::{impact, BodyCollision, BodyEventsPlugin, BodyMerged};
+use field::FieldPlugin;
+use forces::{coulomb_acceleration, Gravity};
 use hud::HUDPlugin;
+use input::{Action, ActionState, ControlsPlugin};
+use labels::LabelsPlugin;
+use maneuver::ManeuverPlugin;
+use menu::MenuPlugin;
+use minimap::MinimapPlugin;
+use motion::{BodyMotion, MotionPlugin};
+use octree::{Octree, TreeBody, Visit};
+use particles::ParticlesPlugin;
 use player::PlayerPlugin;
+use player::SelectedBody;
 use rand::Rng;
+use relativity::{post_newtonian_corrections, PnBody, RelativityPlugin};
+use scenes::ScenesPlugin;
+use serde::{Deserialize, Serialize};
+use ship::ShipPlugin;
+use stars::StarsPlugin;
+use surfaces::SurfacesPlugin;
+use tides::TidesPlugin;
 // use trail_plugin::{Trailed, Trailplugin};
 use utils::*;
-
-const SIZE_SCALE: f32 = 7. / 1000.;
-const DISTANCE_SCALE: f32 = 10.; // for better display
-const TIME_SPEED: f32 = 2332.800; // moon orbit 27 days = 2332800s / 10 for 10 sec rotation // new alg 2332.800
+use vectors::VectorsPlugin;
+use view::{BodyMaterials, SphereMeshes, ViewPlugin};
+
+const SPAWN_RANGE: f32 = 140_000.; // km
+const TIME_SPEED: f32 = 2_332.8; // moon orbit 27 days = 2332800s / 10 for 10 sec rotation // new alg 2332.800
+const INTEGRATOR_NAME: &str = "Velocity Verlet";
+/// Moment of inertia of a uniform sphere, in units of mass times radius²
+const SPHERE_INERTIA: f32 = 0.4;
+/// Above this many bodies far away groups pull as one, below every pair is summed exactly
+const BARNES_HUT_THRESHOLD: usize = 256;
+/// Cells narrower than this fraction of their distance are grouped, lower is more accurate
+const BARNES_HUT_THETA: f32 = 0.7;
 
 fn main() {
     App::new() //
         .insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
-        .insert_resource(GameSpeed {
-            speed: 1.,
-            last_speed: 1.,
-        })
+        .insert_resource(GameSpeed { speed: 1. })
+        .init_resource::<SimStats>()
+        .init_resource::<PhysicsSettings>()
         .add_plugins(DefaultPlugins)
+        .init_state::<AppState>()
         .add_plugins(CommonPlugin)
+        .add_plugins(ControlsPlugin)
         .add_plugins(PlayerPlugin)
+        .add_plugins(ShipPlugin)
+        .add_plugins(ManeuverPlugin)
         .add_plugins(HUDPlugin)
+        .add_plugins(LabelsPlugin)
+        .add_plugins(MinimapPlugin)
+        .add_plugins(FieldPlugin)
+        .add_plugins(VectorsPlugin)
+        .add_plugins(StarsPlugin)
+        .add_plugins(SurfacesPlugin)
+        .add_plugins(EnvironmentPlugin)
+        .add_plugins(TidesPlugin)
+        .add_plugins(DragPlugin)
+        .add_plugins(MotionPlugin)
+        .add_plugins(ParticlesPlugin)
+        .add_plugins(RelativityPlugin)
+        .add_plugins(BodyEventsPlugin)
+        .add_plugins(ViewPlugin)
+        .add_plugins(ScenesPlugin)
+        .add_plugins(MenuPlugin)
         // .add_plugins(Trailplugin)
-        .add_systems(Startup, (setup, setup_planets))
+        .add_systems(Startup, (setup, setup_game_speed))
         .add_systems(
             FixedUpdate,
-            (velocity_verlet, calculate_acceleration_velocity).chain(),
+            (
+                begin_physics_tick,
+                velocity_verlet,
+                calculate_acceleration_velocity,
+                merge_bodies.run_if(|physics: Res<PhysicsSettings>| physics.merge_on_contact),
+                end_physics_tick,
+            )
+                .chain()
+                .run_if(in_state(AppState::Simulating)),
         )
+        .add_systems(First, reset_frame_stats)
+        .add_systems(OnEnter(AppState::Loading), reset_sim_stats)
         // .add_systems(FixedUpdate, (calculate_velocity, move_planets).chain())
-        .add_systems(Update, (toggle_pause, change_speed, spawn_planet_key))
+        .add_systems(
+            Update,
+            (
+                toggle_pause,
+                (change_speed, spawn_planet_key).run_if(in_state(AppState::Simulating)),
+            ),
+        )
         .run();
 }
 
+#[derive(States, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+enum AppState {
+    #[default]
+    MainMenu,
+    /// Replaces the bodies with the [`scenes::CurrentScene`]
+    Loading,
+    Simulating,
+    Paused,
+}
+
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -74,6 +173,7 @@ fn setup(
                 },
                 ..default()
             })
+            .insert(LabLight)
             .with_children(|builder| {
                 builder.spawn(PbrBundle {
                     mesh: meshes.add(Sphere::new(5.).mesh().ico(5).unwrap()),
@@ -89,192 +189,393 @@ fn setup(
     }
 }
 
-fn setup_planets(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut images: ResMut<Assets<Image>>,
-) {
-    let debug_material = StandardMaterial {
-        base_color_texture: Some(images.add(uv_debug_texture())),
-        cull_mode: None,
-        specular_transmission: 0.9,
-        diffuse_transmission: 1.0,
-        thickness: 1.8,
-        ior: 1.5,
-        perceptual_roughness: 0.12,
-        ..default()
+/// Fixed light, replaced by the [`stars::Star`]s of the scene when it has any
+#[derive(Component)]
+struct LabLight;
+
+/// Simulation health, shown on the HUD
+#[derive(Resource, Default)]
+struct SimStats {
+    /// Simulated seconds since the scene was loaded
+    elapsed: f64,
+    /// Pairs of bodies that started touching since the scene was loaded
+    collisions: u32,
+    /// Fixed ticks run during the last frame
+    substeps: u32,
+    substeps_this_frame: u32,
+    /// Wall time of the last physics tick
+    tick_duration: f32,
+    tick_start: Option<Instant>,
+    contacts: HashSet<(Entity, Entity)>,
+}
+
+/// Optional physics of a scene, off in scenes that don't set them
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct PhysicsSettings {
+    gravity: Gravity,
+    /// Touching bodies merge into one, instead of passing through each other
+    merge_on_contact: bool,
+    /// Satellites inside the Roche limit of a much heavier body break into fragments
+    roche_breakup: bool,
+    /// Rate (1/s) at which tides damp radial motion at the surface of the primary, 0 is off
+    tidal_dissipation: f32,
+    /// Fluid filling the scene, slowing everything down
+    medium: Option<Medium>,
+    /// First post-Newtonian correction to gravity, which makes orbits precess
+    post_newtonian: bool,
+    /// km/s, scenes lower it to make relativistic effects visible
+    speed_of_light: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            gravity: Gravity::default(),
+            merge_on_contact: false,
+            roche_breakup: false,
+            tidal_dissipation: 0.,
+            medium: None,
+            post_newtonian: false,
+            speed_of_light: SPEED_OF_LIGHT,
+        }
+    }
+}
+
+fn reset_sim_stats(mut stats: ResMut<SimStats>) {
+    *stats = SimStats::default();
+}
+
+fn reset_frame_stats(mut stats: ResMut<SimStats>) {
+    stats.substeps = stats.substeps_this_frame;
+    stats.substeps_this_frame = 0;
+}
+
+fn begin_physics_tick(mut stats: ResMut<SimStats>) {
+    stats.tick_start = Some(Instant::now());
+}
+
+fn end_physics_tick(time: Res<Time>, game_speed: Res<GameSpeed>, mut stats: ResMut<SimStats>) {
+    if let Some(tick_start) = stats.tick_start.take() {
+        stats.tick_duration = tick_start.elapsed().as_secs_f32();
+    }
+
+    stats.elapsed += f64::from(time.delta_seconds() * TIME_SPEED * game_speed.speed);
+    stats.substeps_this_frame += 1;
+}
+
+fn touching(planet: &Planet, source: &Planet) -> bool {
+    planet.position.distance_squared(source.position) < (planet.radius + source.radius).powi(2)
+}
+
+fn pair_coulomb(planet: &Planet, source: &Planet) -> Vec3 {
+    coulomb_acceleration(
+        planet.position,
+        planet.charge,
+        planet.mass(),
+        source.position,
+        source.charge,
+    )
+}
+
+/// Acceleration (km/s²) of `planet` caused by `source`, `None` when they touch
+fn pair_acceleration(planet: &Planet, source: &Planet, gravity: &Gravity) -> Option<Vec3> {
+    if touching(planet, source) {
+        return None;
+    }
+
+    Some(
+        gravity.acceleration(planet.position, source.position, source.mass())
+            + pair_coulomb(planet, source),
+    )
+}
+
+/// Acceleration (km/s²) of the body at `index` and the bodies it touches, with a higher
+/// entity. Without a tree every pair is summed. With one, far away groups only pull by gravity:
+/// charges can cancel out, so they are summed exactly over the charged bodies.
+fn body_acceleration(
+    index: usize,
+    bodies: &[(Entity, &Planet)],
+    tree: Option<&(Octree, Vec<TreeBody>, Vec<usize>)>,
+    gravity: &Gravity,
+) -> (Vec3, Vec<(Entity, Entity)>) {
+    let (entity, planet) = bodies[index];
+    let mut acceleration = Vec3::ZERO;
+    let mut contacts = Vec::new();
+
+    let Some((tree, tree_bodies, charged)) = tree else {
+        for (j, &(other, source)) in bodies.iter().enumerate() {
+            if index == j {
+                continue;
+            }
+
+            match pair_acceleration(planet, source, gravity) {
+                Some(pull) => acceleration += pull,
+                // Collision
+                None if entity < other => contacts.push((entity, other)),
+                None => {}
+            }
+        }
+        return (acceleration, contacts);
     };
 
-    // commands.spawn(PlanetBundle::new(
-    //     &mut meshes,
-    //     6378.,
-    //     5.51,
-    //     materials.add(Color::BLUE),
-    //     Transform::from_xyz(0.0, 0.0, 0.0),
-    //     None,
-    // ));
-
-    // commands
-    //     .spawn(PlanetBundle::new(
-    //         &mut meshes,
-    //         1737.4,
-    //         3.34,
-    //         materials.add(StandardMaterial{
-    //             base_color: Color::WHITE,
-    //             ..Default::default()
-    //         }),
-    //         Transform::from_xyz(0., 384400. * SIZE_SCALE / DISTANCE_SCALE, 0.0),
-    //         Some(Vec3::new(1., 0., 0.) * 1022. * SIZE_SCALE / DISTANCE_SCALE / 9.), // Some(Vec3::new(1., 0., 0.) * 1022. / 233280. / 10. * 2.)
-    //     ))
-    //     // .insert(Trailed::new())
-    //     ;
-
-    // for _ in 0..500 {
-    //     let x = rand::thread_rng().gen_range(-400f32..400f32);
-    //     let y = rand::thread_rng().gen_range(-400f32..400f32);
-    //     let z = rand::thread_rng().gen_range(-400f32..400f32);
-
-    //     commands.spawn(PlanetBundle::new(
-    //         &mut meshes,
-    //         2500.,
-    //         2.,
-    //         materials.add(Color::WHITE),
-    //         Transform::from_xyz(x, y, z),
-    //         None,
-    //     ));
-    // }
-
-    commands.spawn(PlanetBundle::new(
-        &mut meshes,
-        3000.,
-        8.,
-        materials.add(StandardMaterial {
-            base_color: Color::RED,
-            specular_transmission: 0.9,
-            diffuse_transmission: 1.0,
-            thickness: 1.8,
-            ior: 1.5,
-            perceptual_roughness: 0.12,
-            ..default()
-        }),
-        Transform::from_xyz(-275.0, 13.0, 10.0),
-        None,
-    ));
-
-    commands.spawn(PlanetBundle::new(
-        &mut meshes,
-        3000.,
-        8.,
-        materials.add(StandardMaterial {
-            base_color: Color::GREEN,
-            specular_transmission: 0.9,
-            diffuse_transmission: 1.0,
-            thickness: 1.8,
-            ior: 1.5,
-            perceptual_roughness: 0.12,
-            ..default()
-        }),
-        Transform::from_xyz(125.0, -13.0, 100.0),
-        None,
-    ));
-
-    commands.spawn(PlanetBundle::new(
-        &mut meshes,
-        3000.,
-        8.,
-        materials.add(StandardMaterial {
-            base_color: Color::BLUE,
-            specular_transmission: 0.9,
-            diffuse_transmission: 1.0,
-            thickness: 1.8,
-            ior: 1.5,
-            perceptual_roughness: 0.12,
-            ..default()
-        }),
-        Transform::from_xyz(-175.0, -130.0, -100.0),
-        None,
-    ));
-
-    // ground plane
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Plane3d::default().mesh().size(10000.0, 10000.0)),
-        // material: materials.add(StandardMaterial {
-        //     base_color: Color::GRAY,
-        //     // metallic: 1.,
-        //     cull_mode: None,
-        //     ..Default::default()
-        // }),
-        material: materials.add(debug_material.clone()),
-        transform: Transform::from_xyz(0., -500., 0.),
-        ..default()
+    tree.visit(tree_bodies, index, BARNES_HUT_THETA, |visit| match visit {
+        Visit::Body(j) if j == index => {}
+        Visit::Body(j) => {
+            let (other, source) = bodies[j];
+            if touching(planet, source) {
+                if entity < other {
+                    contacts.push((entity, other));
+                }
+            } else {
+                acceleration +=
+                    gravity.acceleration(planet.position, source.position, tree_bodies[j].mass);
+            }
+        }
+        Visit::Group {
+            mass,
+            center_of_mass,
+        } => acceleration += gravity.acceleration(planet.position, center_of_mass, mass),
     });
 
-    // ceiling plane
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Plane3d::default().mesh().size(10000.0, 10000.0)),
-        material: materials.add(debug_material.clone()),
-        transform: Transform::from_xyz(0., 2500., 0.),
-        ..default()
-    });
+    if planet.charge != 0. {
+        for &j in charged {
+            let source = bodies[j].1;
+            if j != index && !touching(planet, source) {
+                acceleration += pair_coulomb(planet, source);
+            }
+        }
+    }
+
+    (acceleration, contacts)
 }
 
+/// Forces on every body, spread over the compute threads. Large scenes use a Barnes–Hut tree,
+/// see `BARNES_HUT_THRESHOLD`: 10k bodies take about 30 ms on a single thread, under 1% off the
+/// exact sum. The optional post-Newtonian terms are still summed over every pair.
 fn calculate_acceleration_velocity(
     time: Res<Time>,
-    mut planets_query: Query<(&Transform, &mut Planet)>,
+    mut planets_query: Query<(Entity, &mut Planet, Has<BodyMotion>)>,
     game_speed: Res<GameSpeed>,
+    physics: Res<PhysicsSettings>,
+    mut stats: ResMut<SimStats>,
+    mut collisions: EventWriter<BodyCollision>,
 ) {
     let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
-    let mut iter = planets_query.iter_combinations_mut();
-
-    while let Some([(transform1, mut planet1), (transform2, mut planet2)]) = iter.fetch_next() {
-        let delta = transform2.translation - transform1.translation;
-        let distance_sq: f32 = delta.length_squared();
+    let bodies: Vec<(Entity, &Planet)> = planets_query
+        .iter()
+        .map(|(entity, planet, _)| (entity, planet))
+        .collect();
+    let tree = (bodies.len() > BARNES_HUT_THRESHOLD).then(|| {
+        let tree_bodies: Vec<TreeBody> = bodies
+            .iter()
+            .map(|(_, planet)| TreeBody {
+                position: planet.position,
+                mass: planet.mass(),
+                radius: planet.radius,
+            })
+            .collect();
+        let charged: Vec<usize> = bodies
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, planet))| planet.charge != 0.)
+            .map(|(i, _)| i)
+            .collect();
+        (Octree::new(&tree_bodies), tree_bodies, charged)
+    });
 
-        let mass1 = ((4. / 3.) * PI * planet1.radius.powi(3)) * planet1.density;
-        let mass2 = ((4. / 3.) * PI * planet2.radius.powi(3)) * planet2.density;
+    let indices: Vec<usize> = (0..bodies.len()).collect();
+    let forces: Vec<(Vec3, Vec<(Entity, Entity)>)> = indices
+        .par_splat_map(ComputeTaskPool::get(), None, |chunk| {
+            chunk
+                .iter()
+                .map(|&i| body_acceleration(i, &bodies, tree.as_ref(), &physics.gravity))
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let mut contacts = HashSet::new();
+    let mut accelerations: Vec<Vec3> = Vec::with_capacity(forces.len());
+    for (acceleration, body_contacts) in forces {
+        accelerations.push(acceleration);
+        contacts.extend(body_contacts);
+    }
 
-        let mut f = GRAVITY_CONSTANT / (distance_sq / (SIZE_SCALE * DISTANCE_SCALE).powi(2));
+    // Only the bodies that weren't already touching last tick
+    let new_contacts: Vec<(Entity, Entity)> =
+        contacts.difference(&stats.contacts).copied().collect();
+    stats.collisions += new_contacts.len() as u32;
+    for (first, second) in new_contacts {
+        let (Ok((_, a, _)), Ok((_, b, _))) = (planets_query.get(first), planets_query.get(second))
+        else {
+            continue;
+        };
+        let (impact_speed, energy) = impact(a, b);
+        collisions.send(BodyCollision {
+            first,
+            second,
+            impact_speed,
+            energy,
+        });
+    }
 
-        // Collision
-        if distance_sq < ((planet1.radius + planet2.radius) * SIZE_SCALE).powf(2.) {
-            // f = f * (distance_sq / ((planet1.radius + planet2.radius) * SIZE_SCALE).powi(2));
-            // f = -f / 10.;
-            f = 0.;
+    if physics.post_newtonian {
+        // The velocities at the end of the tick aren't known yet, predict them with the
+        // previous acceleration
+        let pn_bodies: Vec<PnBody> = planets_query
+            .iter()
+            .zip(&accelerations)
+            .map(|((_, planet, fixed), &acceleration)| PnBody {
+                position: planet.position,
+                velocity: if fixed {
+                    planet.velocity
+                } else {
+                    planet.velocity + planet.acceleration * dt
+                },
+                gm: physics.gravity.constant * planet.mass(),
+                radius: planet.radius,
+                acceleration,
+            })
+            .collect();
+        let corrections = post_newtonian_corrections(&pn_bodies, physics.speed_of_light);
+        for (acceleration, correction) in accelerations.iter_mut().zip(corrections) {
+            *acceleration += correction;
         }
+    }
 
-        let force_unit_mass = delta.normalize_or_zero() * f;
-
-        let acc1 = force_unit_mass * mass2;
-        let acc2 = -(force_unit_mass * mass1);
-
-        planet1.velocity = planet1.velocity + (planet1.acceleration + acc1) * (dt * 0.5);
-
-        planet2.velocity = planet2.velocity + (planet2.acceleration + acc2) * (dt * 0.5);
+    for ((_, mut planet, fixed), acceleration) in planets_query.iter_mut().zip(accelerations) {
+        // Static and kinematic bodies pull on the others, but their motion is set elsewhere
+        if fixed {
+            planet.acceleration = Vec3::ZERO;
+            continue;
+        }
 
-        planet1.acceleration = acc1;
-        planet2.acceleration = acc2;
+        planet.velocity = planet.velocity + (planet.acceleration + acceleration) * (dt * 0.5);
+        planet.acceleration = acceleration;
     }
+
+    stats.contacts = contacts;
 }
 
 fn velocity_verlet(
     time: Res<Time>,
-    mut planets_query: Query<(&mut Transform, &Planet)>,
+    mut planets_query: Query<(&mut Planet, Has<BodyMotion>)>,
     game_speed: Res<GameSpeed>,
 ) {
     let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
 
-    for (mut transform, planet) in &mut planets_query {
-        transform.translation += planet.velocity * dt + planet.acceleration * (dt * dt * 0.5);
+    for (mut planet, fixed) in &mut planets_query {
+        if !fixed {
+            let step = planet.velocity * dt + planet.acceleration * (dt * dt * 0.5);
+            planet.position += step;
+        }
+
+        // No torques, the spin is constant
+        let turn = Quat::from_scaled_axis(planet.angular_velocity * dt);
+        planet.orientation = (turn * planet.orientation).normalize();
     }
 }
 
-#[derive(Component)]
+/// Merges the bodies touching this tick, keeping the larger one. Mass, momentum, angular
+/// momentum and charge are conserved, the off-center part of the impact ends up as spin.
+/// Static and kinematic bodies absorb what hits them without being moved.
+fn merge_bodies(
+    mut commands: Commands,
+    stats: Res<SimStats>,
+    mut selected: ResMut<SelectedBody>,
+    mut merges: EventWriter<BodyMerged>,
+    mut planets_query: Query<(&mut Planet, Has<BodyMotion>)>,
+) {
+    let mut merged = HashSet::new();
+
+    for &(a, b) in &stats.contacts {
+        if merged.contains(&a) || merged.contains(&b) {
+            continue;
+        }
+        let Ok([(mut first, first_fixed), (mut second, second_fixed)]) =
+            planets_query.get_many_mut([a, b])
+        else {
+            continue;
+        };
+        if first_fixed && second_fixed {
+            continue;
+        }
+        let (survivor, absorbed, fixed) =
+            if second_fixed || (!first_fixed && first.mass() < second.mass()) {
+                std::mem::swap(&mut first, &mut second);
+                (b, a, second_fixed)
+            } else {
+                (a, b, first_fixed)
+            };
+        let (impact_speed, energy) = impact(&first, &second);
+
+        // Weighted by mass fraction, star masses times km overflow f32
+        let mass = first.mass() + second.mass();
+        let (w1, w2) = (first.mass() / mass, second.mass() / mass);
+        let position = first.position * w1 + second.position * w2;
+        let velocity = first.velocity * w1 + second.velocity * w2;
+
+        // Angular momentum per kg of the merged body, spin of each body plus its orbit around
+        // the common center
+        let angular_momentum = [(&*first, w1), (&*second, w2)]
+            .iter()
+            .map(|(planet, weight)| {
+                let orbital = (planet.position - position).cross(planet.velocity - velocity);
+                (SPHERE_INERTIA * planet.radius.powi(2) * planet.angular_velocity + orbital)
+                    * *weight
+            })
+            .sum::<Vec3>();
+
+        // Volumes add up, the density follows from the mass
+        let volume = first.radius.powi(3) + second.radius.powi(3);
+        first.radius = volume.cbrt();
+        first.density = mass / ((4. / 3.) * PI * volume * KG_PER_KM3_PER_G_CM3);
+        if !fixed {
+            first.position = position;
+            first.velocity = velocity;
+            first.acceleration = first.acceleration * w1 + second.acceleration * w2;
+        }
+        first.angular_velocity = angular_momentum / (SPHERE_INERTIA * first.radius.powi(2));
+        first.charge += second.charge;
+
+        merged.extend([survivor, absorbed]);
+        commands.entity(absorbed).despawn_recursive();
+        merges.send(BodyMerged {
+            survivor,
+            absorbed,
+            impact_speed,
+            energy,
+        });
+        if selected.0 == Some(absorbed) {
+            selected.0 = Some(survivor);
+        }
+    }
+}
+
+/// Physical state of a body, in km, km/s and g/cm³.
+/// The `Transform` is only its on-screen representation, see [`view::ViewScale`].
+#[derive(Component, Clone)]
 struct Planet {
     radius: f32,
     density: f32,
+    position: Vec3,
     velocity: Vec3,
     acceleration: Vec3,
+    /// Rotation from the body frame, its spin pole is the local Y axis
+    orientation: Quat,
+    /// rad/s, axis in world space
+    angular_velocity: Vec3,
+    /// Coulombs, like charges repel
+    charge: f32,
+}
+
+impl Planet {
+    /// Mass in kg
+    fn mass(&self) -> f32 {
+        (4. / 3.) * PI * self.radius.powi(3) * self.density * KG_PER_KM3_PER_G_CM3
+    }
 }
 
 #[derive(Bundle)]
@@ -286,12 +587,12 @@ struct PlanetBundle {
 
 impl PlanetBundle {
     fn new(
-        meshes: &mut ResMut<Assets<Mesh>>,
+        sphere_meshes: &SphereMeshes,
         // materials: &mut ResMut<Assets<StandardMaterial>>,
         radius: f32,
         density: f32,
         material: Handle<StandardMaterial>,
-        transform: Transform,
+        position: Vec3,
         initial_velocity: Option<Vec3>,
     ) -> Self {
         PlanetBundle {
@@ -304,38 +605,55 @@ impl PlanetBundle {
             //     ..default()
             // },
             pbr_bundle: PbrBundle {
-                mesh: meshes.add(Sphere::new(radius * SIZE_SCALE).mesh().ico(5).unwrap()),
+                // Swapped for a coarser one by distance, see `view::sync_planet_transforms`
+                mesh: sphere_meshes.finest(),
                 material,
-                transform: transform.with_rotation(Quat::from_rotation_x(-PI / 4.)),
                 ..default()
             },
             planet: Planet {
                 radius,
                 density,
+                position,
                 velocity: initial_velocity.unwrap_or(Vec3::ZERO),
                 acceleration: Vec3::ZERO,
+                orientation: Quat::IDENTITY,
+                angular_velocity: Vec3::ZERO,
+                charge: 0.,
             },
         }
     }
+
+    fn with_spin(mut self, orientation: Quat, angular_velocity: Vec3) -> Self {
+        self.planet.orientation = orientation;
+        self.planet.angular_velocity = angular_velocity;
+        self
+    }
+
+    fn with_charge(mut self, charge: f32) -> Self {
+        self.planet.charge = charge;
+        self
+    }
 }
 
 #[derive(Resource)]
 struct GameSpeed {
     speed: f32,
-    last_speed: f32,
+}
+
+fn setup_game_speed(settings: Res<Settings>, mut game_speed: ResMut<GameSpeed>) {
+    game_speed.speed = settings.default_game_speed;
 }
 
 fn toggle_pause(
-    mut game_speed: ResMut<GameSpeed>,
-    mouse_buttons: Res<ButtonInput<MouseButton>>,
-    // mut virtual_time: ResMut<Time<Virtual>>,
+    actions: Res<ActionState>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
 ) {
-    if mouse_buttons.just_pressed(MouseButton::Left) {
-        if game_speed.speed > 0. {
-            game_speed.last_speed = game_speed.speed;
-            game_speed.speed = 0.;
-        } else {
-            game_speed.speed = game_speed.last_speed;
+    if actions.just_pressed(Action::TogglePause) {
+        match state.get() {
+            AppState::Simulating => next_state.set(AppState::Paused),
+            AppState::Paused => next_state.set(AppState::Simulating),
+            AppState::MainMenu | AppState::Loading => {}
         }
     }
 }
@@ -343,49 +661,56 @@ fn toggle_pause(
 fn change_speed(
     time: Res<Time>,
     mut game_speed: ResMut<GameSpeed>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    settings: Res<Settings>,
     // mut virtual_time: ResMut<Time<Virtual>>,
 ) {
     let mut step = 1.; // per second
 
-    if keyboard_input.pressed(KeyCode::ShiftLeft) {
+    if actions.pressed(Action::Boost) {
         step = 10.;
     }
 
-    if keyboard_input.pressed(KeyCode::KeyQ) {
-        game_speed.speed -= step * time.delta_seconds();
-    }
-
-    if keyboard_input.pressed(KeyCode::KeyE) {
-        game_speed.speed += step * time.delta_seconds();
-    }
+    // Analog triggers change the speed proportionally
+    game_speed.speed +=
+        actions.axis(Action::SlowDown, Action::SpeedUp) * step * time.delta_seconds();
 
-    if keyboard_input.pressed(KeyCode::KeyR) {
-        game_speed.speed = 1.;
+    if actions.pressed(Action::ResetSpeed) {
+        game_speed.speed = settings.default_game_speed;
     }
 }
 
 fn spawn_planet_key(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
+    sphere_meshes: Res<SphereMeshes>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut body_materials: ResMut<BodyMaterials>,
+    actions: Res<ActionState>,
 ) {
-    if keyboard_input.pressed(KeyCode::KeyG) {
+    if actions.pressed(Action::SpawnPlanet) {
         let mut random_g = rand::thread_rng();
-        let x = random_g.gen_range(-1000f32..1000f32);
-        let y = random_g.gen_range(-1000f32..1000f32);
-        let z = random_g.gen_range(-1000f32..1000f32);
+        let x = random_g.gen_range(-SPAWN_RANGE..SPAWN_RANGE);
+        let y = random_g.gen_range(-SPAWN_RANGE..SPAWN_RANGE);
+        let z = random_g.gen_range(-SPAWN_RANGE..SPAWN_RANGE);
 
+        // A small palette keeps the materials shared, and the bodies batched
         let rgb: [f32; 3] = random_g.gen();
-
-        commands.spawn(PlanetBundle::new(
-            &mut meshes,
-            2500.,
-            2.,
-            materials.add(Color::rgb(rgb[0], rgb[1], rgb[2])),
-            Transform::from_xyz(x, y, z),
-            None,
+        let color = Color::rgb(
+            (rgb[0] * 3.).round() / 3.,
+            (rgb[1] * 3.).round() / 3.,
+            (rgb[2] * 3.).round() / 3.,
+        );
+
+        commands.spawn((
+            PlanetBundle::new(
+                &sphere_meshes,
+                2500.,
+                2.,
+                body_materials.get_or_add(color, &mut materials, StandardMaterial::from),
+                Vec3::new(x, y, z),
+                None,
+            ),
+            Name::new("Spawned"),
         ));
     }
 }
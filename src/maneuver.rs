@@ -0,0 +1,457 @@
+use bevy::prelude::*;
+
+use crate::{
+    calculate_acceleration_velocity, end_physics_tick,
+    forces::Gravity,
+    input::{Action, ActionState},
+    motion::BodyMotion,
+    pair_acceleration,
+    player::{CameraMode, MainCamera},
+    ship::Ship,
+    view::ViewScale,
+    AppState, GameSpeed, PhysicsSettings, Planet, SimStats, TIME_SPEED,
+};
+
+/// Physics ticks simulated ahead for the preview
+const PREDICTION_STEPS: usize = 1500;
+/// Heaviest bodies moved in the preview, the ship is pulled by these and the reference only
+const PREDICTION_SOURCES: usize = 8;
+/// Physics ticks between two predictions while their inputs stay the same
+const PREDICTION_INTERVAL: u32 = 32;
+/// km/s added per real second while holding a handle key
+const HANDLE_RATE: f32 = 0.1;
+/// Boost multiplies the handle rate
+const HANDLE_BOOST: f32 = 10.;
+/// Fraction of the predicted span the node slides per real second
+const NODE_SLIDE_RATE: f64 = 0.05;
+/// Handle length, as a fraction of the camera distance to the node
+const HANDLE_SIZE: f32 = 0.08;
+/// km/s drawn as a full handle length
+const HANDLE_DELTA_V: f32 = 0.5;
+
+pub struct ManeuverPlugin;
+
+impl Plugin for ManeuverPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<ManeuverPlanner>()
+            .add_systems(OnEnter(AppState::Loading), clear_planner)
+            .add_systems(
+                Update,
+                (edit_maneuver_node, predict_trajectory, draw_maneuver)
+                    .chain()
+                    .run_if(in_state(AppState::Simulating))
+                    .run_if(resource_equals(CameraMode::Ship)),
+            )
+            .add_systems(
+                FixedUpdate,
+                execute_maneuver
+                    .after(calculate_acceleration_velocity)
+                    .before(end_physics_tick)
+                    .run_if(in_state(AppState::Simulating))
+                    .run_if(resource_equals(CameraMode::Ship)),
+            );
+    }
+}
+
+/// Planned burn of the ship
+#[derive(Clone, Copy)]
+pub struct ManeuverNode {
+    /// Simulation time of the burn, in seconds
+    pub time: f64,
+    /// km/s along prograde, normal and radial out
+    pub delta_v: Vec3,
+    /// Body the burn directions are relative to
+    reference: Entity,
+    ship: Entity,
+}
+
+#[derive(Resource, Default)]
+pub struct ManeuverPlanner {
+    pub node: Option<ManeuverNode>,
+    /// Body pulling hardest on the ship, new nodes are relative to it
+    reference: Option<Entity>,
+    /// Simulation time and offset from the reference body of the predicted ship path, in km
+    offsets: Vec<(f64, Vec3)>,
+    /// `offsets` in world space, around the reference body where it is now
+    path: Vec<(f64, Vec3)>,
+    burn: Option<Burn>,
+    /// Inputs of the current prediction and the simulation time it started from
+    predicted: Option<(PredictionKey, f64)>,
+}
+
+/// Index of the burn in the predicted path and the burn directions there
+type Burn = (usize, [Vec3; 3]);
+
+/// Everything the prediction depends on apart from the bodies moving on
+#[derive(Clone, Copy, PartialEq)]
+struct PredictionKey {
+    ship: Entity,
+    reference: Entity,
+    /// Time and delta-v of the node
+    node: Option<(f64, Vec3)>,
+    /// Changes while the engines fire
+    fuel: f32,
+    dt: f32,
+}
+
+fn clear_planner(mut planner: ResMut<ManeuverPlanner>) {
+    *planner = ManeuverPlanner::default();
+}
+
+/// Prograde, normal and radial out directions of `ship` in its orbit around `reference`
+fn burn_directions(ship: &Planet, reference: &Planet) -> [Vec3; 3] {
+    let velocity = ship.velocity - reference.velocity;
+    let prograde = velocity.try_normalize().unwrap_or(Vec3::NEG_Z);
+    let normal = (ship.position - reference.position)
+        .cross(velocity)
+        .try_normalize()
+        .unwrap_or_else(|| prograde.any_orthonormal_vector());
+
+    [prograde, normal, prograde.cross(normal)]
+}
+
+fn burn_vector(directions: [Vec3; 3], delta_v: Vec3) -> Vec3 {
+    directions[0] * delta_v.x + directions[1] * delta_v.y + directions[2] * delta_v.z
+}
+
+/// N places a node on the predicted path where the crosshair points, or removes it.
+/// The handle keys change the burn, the slide keys move the node along the path.
+fn edit_maneuver_node(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    stats: Res<SimStats>,
+    mut planner: ResMut<ManeuverPlanner>,
+    camera_q: Query<&Transform, With<MainCamera>>,
+    ship_q: Query<Entity, With<Ship>>,
+) {
+    if actions.just_pressed(Action::ManeuverNode) {
+        if planner.node.is_some() {
+            planner.node = None;
+        } else {
+            let camera = camera_q.single();
+            let alignment = |point: Vec3| {
+                (point - camera.translation)
+                    .normalize_or_zero()
+                    .dot(*camera.forward())
+            };
+            let closest = planner
+                .path
+                .iter()
+                .max_by(|(_, a), (_, b)| alignment(*a).total_cmp(&alignment(*b)));
+
+            if let (Some(&(time, _)), Some(reference), Ok(ship)) =
+                (closest, planner.reference, ship_q.get_single())
+            {
+                planner.node = Some(ManeuverNode {
+                    time,
+                    delta_v: Vec3::ZERO,
+                    reference,
+                    ship,
+                });
+            }
+        }
+    }
+
+    let span = planner
+        .path
+        .last()
+        .map_or(0., |(end, _)| end - stats.elapsed);
+    let Some(node) = planner.node.as_mut() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+
+    let boost = if actions.pressed(Action::Boost) {
+        HANDLE_BOOST
+    } else {
+        1.
+    };
+    let handles = Vec3::new(
+        actions.axis(Action::NodeRetrograde, Action::NodePrograde),
+        actions.axis(Action::NodeAntiNormal, Action::NodeNormal),
+        actions.axis(Action::NodeRadialIn, Action::NodeRadialOut),
+    );
+    node.delta_v += handles * HANDLE_RATE * boost * dt;
+
+    let slide = actions.axis(Action::NodeEarlier, Action::NodeLater);
+    node.time += f64::from(slide * dt) * span * NODE_SLIDE_RATE;
+    node.time = node.time.max(stats.elapsed);
+}
+
+/// Keeps the planner in sync with the ship and its reference body, and redoes the prediction
+/// when its inputs change or every `PREDICTION_INTERVAL` ticks. The path is drawn around the
+/// reference body where it is now.
+fn predict_trajectory(
+    fixed_time: Res<Time<Fixed>>,
+    game_speed: Res<GameSpeed>,
+    stats: Res<SimStats>,
+    physics: Res<PhysicsSettings>,
+    view_scale: Res<ViewScale>,
+    mut planner: ResMut<ManeuverPlanner>,
+    planets_q: Query<(Entity, &Planet, Option<&BodyMotion>, Option<&Ship>)>,
+) {
+    let Some((ship_entity, ship_planet, ship)) = planets_q
+        .iter()
+        .find_map(|(entity, planet, _, ship)| Some((entity, planet, ship?)))
+    else {
+        *planner = ManeuverPlanner::default();
+        return;
+    };
+
+    // Heaviest pull on the ship
+    let pull =
+        |planet: &Planet| planet.mass() / planet.position.distance_squared(ship_planet.position);
+    planner.reference = planets_q
+        .iter()
+        .filter(|(entity, ..)| *entity != ship_entity)
+        .max_by(|(_, a, ..), (_, b, ..)| pull(a).total_cmp(&pull(b)))
+        .map(|(entity, ..)| entity);
+
+    if planner
+        .node
+        .is_some_and(|node| node.ship != ship_entity || planets_q.get(node.reference).is_err())
+    {
+        planner.node = None;
+    }
+    let Some((reference, reference_planet, ..)) = planner
+        .node
+        .map(|node| node.reference)
+        .or(planner.reference)
+        .and_then(|reference| planets_q.get(reference).ok())
+    else {
+        planner.path.clear();
+        planner.offsets.clear();
+        planner.burn = None;
+        return;
+    };
+
+    let dt = fixed_time.timestep().as_secs_f32() * TIME_SPEED * game_speed.speed;
+    if dt <= 0. {
+        return;
+    }
+
+    let key = PredictionKey {
+        ship: ship_entity,
+        reference,
+        node: planner.node.map(|node| (node.time, node.delta_v)),
+        fuel: ship.fuel,
+        dt,
+    };
+    let stale = planner.predicted.is_none_or(|(last, time)| {
+        last != key || stats.elapsed - time >= f64::from(dt) * f64::from(PREDICTION_INTERVAL)
+    });
+    if stale {
+        let (offsets, burn) = simulate_path(
+            &planets_q,
+            ship_entity,
+            reference,
+            planner.node,
+            ship.delta_v(),
+            &physics.gravity,
+            dt,
+            stats.elapsed,
+        );
+        planner.offsets = offsets;
+        planner.burn = burn;
+        planner.predicted = Some((key, stats.elapsed));
+    }
+
+    let anchor = reference_planet.position;
+    let path = planner
+        .offsets
+        .iter()
+        .map(|&(time, offset)| (time, view_scale.to_world(anchor + offset)))
+        .collect();
+    planner.path = path;
+}
+
+/// Runs the ship ahead as a test particle, with the same velocity Verlet steps as the
+/// `FixedUpdate` systems and the planned burn on the way. Only the `PREDICTION_SOURCES`
+/// heaviest bodies and the reference move and pull, on each other and on the ship, so the cost
+/// doesn't grow with the body count. Drag, tides and the post-Newtonian terms are left out.
+/// Returns the ship offsets from the reference body, and where the burn happens.
+#[allow(clippy::too_many_arguments)]
+fn simulate_path(
+    planets_q: &Query<(Entity, &Planet, Option<&BodyMotion>, Option<&Ship>)>,
+    ship_entity: Entity,
+    reference: Entity,
+    node: Option<ManeuverNode>,
+    available_delta_v: f32,
+    gravity: &Gravity,
+    dt: f32,
+    start: f64,
+) -> (Vec<(f64, Vec3)>, Option<Burn>) {
+    let Ok((_, ship, ..)) = planets_q.get(ship_entity) else {
+        return (Vec::new(), None);
+    };
+    let mut ship = ship.clone();
+
+    let mut sources: Vec<(Entity, Planet, Option<BodyMotion>)> = planets_q
+        .iter()
+        .filter(|(entity, ..)| *entity != ship_entity)
+        .map(|(entity, planet, motion, _)| (entity, planet.clone(), motion.cloned()))
+        .collect();
+    sources.sort_by(|(_, a, _), (_, b, _)| b.mass().total_cmp(&a.mass()));
+    let reference_index = sources
+        .iter()
+        .position(|(entity, ..)| *entity == reference)
+        .unwrap_or_default();
+    // The reference goes first
+    let reference_body = sources.remove(reference_index);
+    sources.insert(0, reference_body);
+    sources.truncate(PREDICTION_SOURCES);
+
+    let mut offsets = Vec::with_capacity(PREDICTION_STEPS);
+    let mut burn = None;
+    let mut pending_burn = node;
+    let mut time = start;
+
+    for step in 0..PREDICTION_STEPS {
+        time += f64::from(dt);
+
+        for (_, planet, motion) in &mut sources {
+            match motion {
+                None => {
+                    planet.position += planet.velocity * dt + planet.acceleration * (dt * dt * 0.5);
+                }
+                Some(motion) => {
+                    if let Some((position, velocity)) = motion.sample(time) {
+                        planet.position = position;
+                        planet.velocity = velocity;
+                    }
+                }
+            }
+        }
+        ship.position += ship.velocity * dt + ship.acceleration * (dt * dt * 0.5);
+
+        let accelerations: Vec<Vec3> = sources
+            .iter()
+            .enumerate()
+            .map(|(i, (_, planet, _))| {
+                sources
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| i != j)
+                    .filter_map(|(_, (_, source, _))| pair_acceleration(planet, source, gravity))
+                    .sum()
+            })
+            .collect();
+        for ((_, planet, motion), acceleration) in sources.iter_mut().zip(accelerations) {
+            if motion.is_some() {
+                planet.acceleration = Vec3::ZERO;
+                continue;
+            }
+
+            planet.velocity += (planet.acceleration + acceleration) * (dt * 0.5);
+            planet.acceleration = acceleration;
+        }
+
+        // The path ends where the ship hits something
+        let mut crashed = false;
+        let acceleration: Vec3 = sources
+            .iter()
+            .filter_map(|(_, source, _)| {
+                let pull = pair_acceleration(&ship, source, gravity);
+                crashed |= pull.is_none();
+                pull
+            })
+            .sum();
+        ship.velocity += (ship.acceleration + acceleration) * (dt * 0.5);
+        ship.acceleration = acceleration;
+
+        // Same timing as `execute_maneuver`
+        if let Some(node) = pending_burn.filter(|node| time >= node.time) {
+            let directions = burn_directions(&ship, &sources[0].1);
+            ship.velocity +=
+                burn_vector(directions, node.delta_v).clamp_length_max(available_delta_v);
+            burn = Some((step, directions));
+            pending_burn = None;
+        }
+
+        offsets.push((time, ship.position - sources[0].1.position));
+        if crashed {
+            break;
+        }
+    }
+
+    (offsets, burn)
+}
+
+/// Path before the burn in grey, after it in orange. Each handle shows its burn direction,
+/// with an arrow as long as the planned delta-v along it.
+fn draw_maneuver(
+    mut gizmos: Gizmos,
+    planner: Res<ManeuverPlanner>,
+    camera_q: Query<&Transform, With<MainCamera>>,
+) {
+    let split = planner
+        .burn
+        .map_or(planner.path.len(), |(index, _)| index + 1);
+    gizmos.linestrip(
+        planner.path[..split].iter().map(|(_, point)| *point),
+        Color::GRAY,
+    );
+
+    let (Some((index, directions)), Some(node)) = (planner.burn, planner.node) else {
+        return;
+    };
+    gizmos.linestrip(
+        planner.path[index..].iter().map(|(_, point)| *point),
+        Color::ORANGE,
+    );
+
+    let position = planner.path[index].1;
+    let size = camera_q.single().translation.distance(position) * HANDLE_SIZE;
+    gizmos.sphere(position, Quat::IDENTITY, size * 0.15, Color::WHITE);
+
+    let colors = [Color::YELLOW, Color::FUCHSIA, Color::CYAN];
+    for ((direction, delta_v), color) in directions
+        .into_iter()
+        .zip(node.delta_v.to_array())
+        .zip(colors)
+    {
+        gizmos.line(
+            position - direction * size,
+            position + direction * size,
+            color.with_a(0.4),
+        );
+        if delta_v != 0. {
+            let length = size * delta_v / HANDLE_DELTA_V;
+            gizmos.arrow(position, position + direction * length, color);
+        }
+    }
+}
+
+/// Burns at the node as a single impulse, like the preview, then clears the node
+fn execute_maneuver(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    stats: Res<SimStats>,
+    mut planner: ResMut<ManeuverPlanner>,
+    mut ship_q: Query<(Entity, &mut Ship, &mut Planet)>,
+    planets_q: Query<&Planet, Without<Ship>>,
+) {
+    let Some(node) = planner.node else {
+        return;
+    };
+    // `SimStats::elapsed` only counts this tick once it ends
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+    if stats.elapsed + f64::from(dt) < node.time {
+        return;
+    }
+
+    planner.node = None;
+    let (Ok((entity, mut ship, mut planet)), Ok(reference)) =
+        (ship_q.get_single_mut(), planets_q.get(node.reference))
+    else {
+        return;
+    };
+    if entity != node.ship {
+        return;
+    }
+
+    let burn = burn_vector(burn_directions(&planet, reference), node.delta_v);
+    let gained = ship.burn(burn.length());
+    planet.velocity += burn.normalize_or_zero() * gained;
+}
@@ -0,0 +1,666 @@
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+use crate::{
+    common::{ScreenMode, Settings, MIN_BASE_SPEED, MIN_GAME_SPEED, MIN_MOUSE_SENSITIVITY},
+    drag::{Atmosphere, Medium, MAX_DRAG_RATE, MAX_GAS_DENSITY, MIN_DRAG_RATE, MIN_GAS_DENSITY},
+    environment::Environment,
+    forces::{
+        ForceLaw, MAX_EXPONENT, MAX_GRAVITY_CONSTANT, MIN_EXPONENT, MIN_GRAVITY_CONSTANT,
+        MIN_LENGTH,
+    },
+    motion::BodyMotion,
+    particles::TestParticles,
+    scenes::{
+        builtin_scenes, save_scene, saved_scenes, BodyDescription, CurrentScene, SceneFile,
+        SceneSource,
+    },
+    ship::Ship,
+    stars::Star,
+    surfaces::Surface,
+    AppState, PhysicsSettings, Planet, SimStats,
+};
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON_COLOR: Color = Color::rgb(0.35, 0.55, 0.35);
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<Menu>()
+            .add_systems(OnEnter(AppState::MainMenu), open_menu)
+            .add_systems(OnEnter(AppState::Paused), open_menu)
+            .add_systems(OnExit(AppState::MainMenu), close_menu)
+            .add_systems(OnExit(AppState::Paused), close_menu)
+            .add_systems(
+                Update,
+                (
+                    menu_buttons,
+                    button_colors,
+                    rebuild_menu.run_if(resource_changed::<Menu>),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::MainMenu).or_else(in_state(AppState::Paused))),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuPage {
+    Root,
+    SceneBrowser,
+    Settings,
+    Hud,
+    Physics,
+}
+
+#[derive(Resource)]
+struct Menu {
+    page: MenuPage,
+    /// Feedback shown under the buttons, like where a scene was saved
+    message: Option<String>,
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Menu {
+            page: MenuPage::Root,
+            message: None,
+        }
+    }
+}
+
+#[derive(Component)]
+struct MenuRoot;
+
+#[derive(Component, Clone)]
+enum MenuButton {
+    Start,
+    Resume,
+    Restart,
+    Page(MenuPage),
+    LoadScene(SceneSource),
+    SaveScene,
+    MainMenu,
+    Quit,
+    Setting(SettingChange),
+}
+
+#[derive(Clone, Copy)]
+enum SettingChange {
+    MouseSensitivity(f32),
+    InvertY,
+    BaseSpeed(f32),
+    DefaultGameSpeed(f32),
+    Fullscreen,
+    ShowFps,
+    ShowSpeed,
+    ShowClock,
+    ShowBodies,
+    ShowPhysics,
+    ShowLabels,
+    ShowMinimap,
+    ShowEventLog,
+    ShowShip,
+    LogEventsToFile,
+    /// Multiplies G
+    GravityConstant(f32),
+    ForceLaw,
+    /// Multiplies the length of the force law
+    ForceLength(f32),
+    ForceExponent(f32),
+    MergeOnContact,
+    Medium,
+    /// Multiplies the rate or density of the medium
+    MediumStrength(f32),
+}
+
+fn open_menu(mut menu: ResMut<Menu>, mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    *menu = Menu::default();
+
+    // Menus need the cursor
+    let mut primary_window = windows.single_mut();
+    primary_window.cursor.grab_mode = CursorGrabMode::None;
+    primary_window.cursor.visible = true;
+}
+
+fn close_menu(mut commands: Commands, menu_q: Query<Entity, With<MenuRoot>>) {
+    for entity in &menu_q {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn rebuild_menu(
+    mut commands: Commands,
+    menu: Res<Menu>,
+    state: Res<State<AppState>>,
+    settings: Res<Settings>,
+    screen_mode: Res<ScreenMode>,
+    physics: Res<PhysicsSettings>,
+    menu_q: Query<Entity, With<MenuRoot>>,
+) {
+    for entity in &menu_q {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(8.),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.6).into(),
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .insert(MenuRoot)
+        .with_children(|parent| {
+            match (menu.page, state.get()) {
+                (MenuPage::Root, AppState::MainMenu) => {
+                    spawn_title(parent, "Playground");
+                    spawn_button(parent, "Start", MenuButton::Start);
+                    spawn_button(
+                        parent,
+                        "Load scene",
+                        MenuButton::Page(MenuPage::SceneBrowser),
+                    );
+                    spawn_button(parent, "Settings", MenuButton::Page(MenuPage::Settings));
+                    spawn_button(parent, "Quit", MenuButton::Quit);
+                }
+                (MenuPage::Root, _) => {
+                    spawn_title(parent, "Paused");
+                    spawn_button(parent, "Resume", MenuButton::Resume);
+                    spawn_button(parent, "Restart", MenuButton::Restart);
+                    spawn_button(
+                        parent,
+                        "Load scene",
+                        MenuButton::Page(MenuPage::SceneBrowser),
+                    );
+                    spawn_button(parent, "Save scene", MenuButton::SaveScene);
+                    spawn_button(parent, "Physics", MenuButton::Page(MenuPage::Physics));
+                    spawn_button(parent, "Settings", MenuButton::Page(MenuPage::Settings));
+                    spawn_button(parent, "Main menu", MenuButton::MainMenu);
+                    spawn_button(parent, "Quit", MenuButton::Quit);
+                }
+                (MenuPage::SceneBrowser, _) => {
+                    spawn_title(parent, "Scenes");
+
+                    for (index, scene) in builtin_scenes().iter().enumerate() {
+                        spawn_button(
+                            parent,
+                            &scene.name,
+                            MenuButton::LoadScene(SceneSource::BuiltIn(index)),
+                        );
+                    }
+
+                    for path in saved_scenes() {
+                        let label = path
+                            .file_stem()
+                            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+                        spawn_button(
+                            parent,
+                            &label,
+                            MenuButton::LoadScene(SceneSource::File(path)),
+                        );
+                    }
+
+                    spawn_button(parent, "Back", MenuButton::Page(MenuPage::Root));
+                }
+                (MenuPage::Settings, _) => {
+                    spawn_title(parent, "Settings");
+
+                    spawn_setting(
+                        parent,
+                        &format!("Mouse sensitivity: {:.2}", settings.mouse_sensitivity),
+                        SettingChange::MouseSensitivity(-0.05),
+                        SettingChange::MouseSensitivity(0.05),
+                    );
+                    spawn_setting(
+                        parent,
+                        &format!("Camera speed: {:.0}", settings.base_speed),
+                        SettingChange::BaseSpeed(-50.),
+                        SettingChange::BaseSpeed(50.),
+                    );
+                    spawn_setting(
+                        parent,
+                        &format!("Default game speed: x{:.2}", settings.default_game_speed),
+                        SettingChange::DefaultGameSpeed(-0.25),
+                        SettingChange::DefaultGameSpeed(0.25),
+                    );
+
+                    let on_off = |on: bool| if on { "on" } else { "off" };
+                    spawn_button(
+                        parent,
+                        &format!("Invert Y: {}", on_off(settings.invert_y)),
+                        MenuButton::Setting(SettingChange::InvertY),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!(
+                            "Fullscreen: {}",
+                            on_off(*screen_mode == ScreenMode::BorderlessFullscreen)
+                        ),
+                        MenuButton::Setting(SettingChange::Fullscreen),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Event log file: {}", on_off(settings.log_events_to_file)),
+                        MenuButton::Setting(SettingChange::LogEventsToFile),
+                    );
+                    spawn_button(parent, "HUD", MenuButton::Page(MenuPage::Hud));
+
+                    spawn_button(parent, "Back", MenuButton::Page(MenuPage::Root));
+                }
+                (MenuPage::Hud, _) => {
+                    spawn_title(parent, "HUD");
+
+                    let on_off = |on: bool| if on { "on" } else { "off" };
+                    spawn_button(
+                        parent,
+                        &format!("Show FPS: {}", on_off(settings.hud.show_fps)),
+                        MenuButton::Setting(SettingChange::ShowFps),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show speed: {}", on_off(settings.hud.show_speed)),
+                        MenuButton::Setting(SettingChange::ShowSpeed),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show clock: {}", on_off(settings.hud.show_clock)),
+                        MenuButton::Setting(SettingChange::ShowClock),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show bodies: {}", on_off(settings.hud.show_bodies)),
+                        MenuButton::Setting(SettingChange::ShowBodies),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show physics: {}", on_off(settings.hud.show_physics)),
+                        MenuButton::Setting(SettingChange::ShowPhysics),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show labels: {}", on_off(settings.hud.show_labels)),
+                        MenuButton::Setting(SettingChange::ShowLabels),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show minimap: {}", on_off(settings.hud.show_minimap)),
+                        MenuButton::Setting(SettingChange::ShowMinimap),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show event log: {}", on_off(settings.hud.show_event_log)),
+                        MenuButton::Setting(SettingChange::ShowEventLog),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Show ship: {}", on_off(settings.hud.show_ship)),
+                        MenuButton::Setting(SettingChange::ShowShip),
+                    );
+
+                    spawn_button(parent, "Back", MenuButton::Page(MenuPage::Settings));
+                }
+                (MenuPage::Physics, _) => {
+                    spawn_title(parent, "Physics");
+
+                    spawn_setting(
+                        parent,
+                        &format!("G: {:.3e} km³/(kg s²)", physics.gravity.constant),
+                        SettingChange::GravityConstant(0.5),
+                        SettingChange::GravityConstant(2.),
+                    );
+                    spawn_button(
+                        parent,
+                        &format!("Force law: {}", physics.gravity.law),
+                        MenuButton::Setting(SettingChange::ForceLaw),
+                    );
+                    match physics.gravity.law {
+                        ForceLaw::Newtonian => {}
+                        ForceLaw::Softened { length } => spawn_setting(
+                            parent,
+                            &format!("Softening: {length:.0} km"),
+                            SettingChange::ForceLength(0.5),
+                            SettingChange::ForceLength(2.),
+                        ),
+                        ForceLaw::Yukawa { range } => spawn_setting(
+                            parent,
+                            &format!("Range: {range:.0} km"),
+                            SettingChange::ForceLength(0.5),
+                            SettingChange::ForceLength(2.),
+                        ),
+                        ForceLaw::InversePower {
+                            exponent,
+                            reference,
+                        } => {
+                            spawn_setting(
+                                parent,
+                                &format!("Exponent: {exponent:.2}"),
+                                SettingChange::ForceExponent(-0.25),
+                                SettingChange::ForceExponent(0.25),
+                            );
+                            spawn_setting(
+                                parent,
+                                &format!("Reference distance: {reference:.0} km"),
+                                SettingChange::ForceLength(0.5),
+                                SettingChange::ForceLength(2.),
+                            );
+                        }
+                    }
+
+                    spawn_button(
+                        parent,
+                        &format!(
+                            "Merge on contact: {}",
+                            if physics.merge_on_contact {
+                                "on"
+                            } else {
+                                "off"
+                            }
+                        ),
+                        MenuButton::Setting(SettingChange::MergeOnContact),
+                    );
+                    spawn_button(
+                        parent,
+                        &physics
+                            .medium
+                            .map_or("Medium: vacuum".to_string(), |medium| {
+                                format!("Medium: {medium}")
+                            }),
+                        MenuButton::Setting(SettingChange::Medium),
+                    );
+                    if physics.medium.is_some() {
+                        spawn_setting(
+                            parent,
+                            "Medium strength",
+                            SettingChange::MediumStrength(0.1),
+                            SettingChange::MediumStrength(10.),
+                        );
+                    }
+
+                    spawn_button(parent, "Back", MenuButton::Page(MenuPage::Root));
+                }
+            }
+
+            if let Some(message) = &menu.message {
+                parent.spawn(TextBundle::from_section(
+                    message.clone(),
+                    TextStyle {
+                        font_size: 18.0,
+                        color: Color::GRAY,
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
+fn spawn_title(parent: &mut ChildBuilder, title: &str) {
+    parent.spawn(
+        TextBundle::from_section(
+            title,
+            TextStyle {
+                font_size: 50.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::bottom(Val::Px(20.)),
+            ..default()
+        }),
+    );
+}
+
+fn spawn_button(parent: &mut ChildBuilder, label: &str, button: MenuButton) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                min_width: Val::Px(250.),
+                padding: UiRect::axes(Val::Px(20.), Val::Px(8.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BUTTON_COLOR.into(),
+            ..default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 25.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Label with - and + buttons
+fn spawn_setting(
+    parent: &mut ChildBuilder,
+    label: &str,
+    decrease: SettingChange,
+    increase: SettingChange,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(8.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            spawn_button(parent, "-", MenuButton::Setting(decrease));
+            parent.spawn(
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font_size: 25.0,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    min_width: Val::Px(320.),
+                    ..default()
+                }),
+            );
+            spawn_button(parent, "+", MenuButton::Setting(increase));
+        });
+}
+
+fn button_colors(mut buttons_q: Query<(&Interaction, &mut BackgroundColor), Changed<Interaction>>) {
+    for (interaction, mut background) in &mut buttons_q {
+        *background = match interaction {
+            Interaction::Pressed => PRESSED_BUTTON_COLOR,
+            Interaction::Hovered => HOVERED_BUTTON_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }
+        .into();
+    }
+}
+
+/// Everything `SaveScene` reads from a body
+type SavedBody<'a> = (
+    &'a Name,
+    &'a Planet,
+    &'a Handle<StandardMaterial>,
+    Option<&'a Star>,
+    Option<&'a Surface>,
+    Option<&'a BodyMotion>,
+    Option<&'a Atmosphere>,
+);
+
+#[allow(clippy::too_many_arguments)]
+fn menu_buttons(
+    buttons_q: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    planets_q: Query<SavedBody, Without<Ship>>,
+    materials: Res<Assets<StandardMaterial>>,
+    environment: Res<Environment>,
+    mut physics: ResMut<PhysicsSettings>,
+    particles: Res<TestParticles>,
+    stats: Res<SimStats>,
+    mut menu: ResMut<Menu>,
+    mut settings: ResMut<Settings>,
+    mut screen_mode: ResMut<ScreenMode>,
+    mut current_scene: ResMut<CurrentScene>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    for (interaction, button) in &buttons_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match button {
+            MenuButton::Start | MenuButton::Restart => next_state.set(AppState::Loading),
+            MenuButton::Resume => next_state.set(AppState::Simulating),
+            MenuButton::Page(page) => {
+                menu.page = *page;
+                menu.message = None;
+            }
+            MenuButton::LoadScene(source) => {
+                current_scene.0 = source.clone();
+                next_state.set(AppState::Loading);
+            }
+            MenuButton::SaveScene => {
+                let scene = SceneFile {
+                    name: "Saved scene".to_string(),
+                    environment: *environment,
+                    physics: physics.clone(),
+                    bodies: planets_q
+                        .iter()
+                        .map(
+                            |(name, planet, material, star, surface, motion, atmosphere)| {
+                                BodyDescription {
+                                    name: name.to_string(),
+                                    radius: planet.radius,
+                                    density: planet.density,
+                                    position: planet.position,
+                                    velocity: planet.velocity,
+                                    color: materials
+                                        .get(material)
+                                        .map_or(Color::WHITE, |material| material.base_color),
+                                    luminosity: star.map(|star| star.luminosity),
+                                    surface: surface.copied(),
+                                    orientation: planet.orientation,
+                                    angular_velocity: planet.angular_velocity,
+                                    motion: motion.map(|motion| motion.advanced(stats.elapsed)),
+                                    charge: planet.charge,
+                                    atmosphere: atmosphere.copied(),
+                                }
+                            },
+                        )
+                        .collect(),
+                    particles: particles.to_clouds(),
+                };
+
+                menu.message = Some(match save_scene(&scene) {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(err) => format!("Could not save scene: {err}"),
+                });
+            }
+            MenuButton::MainMenu => next_state.set(AppState::MainMenu),
+            MenuButton::Quit => {
+                app_exit_events.send(AppExit);
+            }
+            MenuButton::Setting(change) => {
+                match *change {
+                    SettingChange::MouseSensitivity(step) => {
+                        settings.mouse_sensitivity =
+                            (settings.mouse_sensitivity + step).max(MIN_MOUSE_SENSITIVITY);
+                    }
+                    SettingChange::InvertY => settings.invert_y = !settings.invert_y,
+                    SettingChange::BaseSpeed(step) => {
+                        settings.base_speed = (settings.base_speed + step).max(MIN_BASE_SPEED);
+                    }
+                    SettingChange::DefaultGameSpeed(step) => {
+                        settings.default_game_speed =
+                            (settings.default_game_speed + step).max(MIN_GAME_SPEED);
+                    }
+                    SettingChange::Fullscreen => {
+                        *screen_mode = match *screen_mode {
+                            ScreenMode::Windowed => ScreenMode::BorderlessFullscreen,
+                            ScreenMode::BorderlessFullscreen => ScreenMode::Windowed,
+                        };
+                    }
+                    SettingChange::ShowFps => settings.hud.show_fps = !settings.hud.show_fps,
+                    SettingChange::ShowSpeed => {
+                        settings.hud.show_speed = !settings.hud.show_speed;
+                    }
+                    SettingChange::ShowClock => {
+                        settings.hud.show_clock = !settings.hud.show_clock;
+                    }
+                    SettingChange::ShowBodies => {
+                        settings.hud.show_bodies = !settings.hud.show_bodies;
+                    }
+                    SettingChange::ShowPhysics => {
+                        settings.hud.show_physics = !settings.hud.show_physics;
+                    }
+                    SettingChange::ShowLabels => {
+                        settings.hud.show_labels = !settings.hud.show_labels;
+                    }
+                    SettingChange::ShowMinimap => {
+                        settings.hud.show_minimap = !settings.hud.show_minimap;
+                    }
+                    SettingChange::ShowEventLog => {
+                        settings.hud.show_event_log = !settings.hud.show_event_log;
+                    }
+                    SettingChange::ShowShip => settings.hud.show_ship = !settings.hud.show_ship,
+                    SettingChange::LogEventsToFile => {
+                        settings.log_events_to_file = !settings.log_events_to_file;
+                    }
+                    SettingChange::GravityConstant(factor) => {
+                        physics.gravity.constant = (physics.gravity.constant * factor)
+                            .clamp(MIN_GRAVITY_CONSTANT, MAX_GRAVITY_CONSTANT);
+                    }
+                    SettingChange::ForceLaw => physics.gravity.law = physics.gravity.law.next(),
+                    SettingChange::ForceLength(factor) => match &mut physics.gravity.law {
+                        ForceLaw::Newtonian => {}
+                        ForceLaw::Softened { length: value }
+                        | ForceLaw::Yukawa { range: value }
+                        | ForceLaw::InversePower {
+                            reference: value, ..
+                        } => *value = (*value * factor).max(MIN_LENGTH),
+                    },
+                    SettingChange::MergeOnContact => {
+                        physics.merge_on_contact = !physics.merge_on_contact;
+                    }
+                    SettingChange::Medium => physics.medium = Medium::next(physics.medium),
+                    SettingChange::MediumStrength(factor) => match &mut physics.medium {
+                        None => {}
+                        Some(Medium::Linear { rate }) => {
+                            *rate = (*rate * factor).clamp(MIN_DRAG_RATE, MAX_DRAG_RATE);
+                        }
+                        Some(Medium::Quadratic { density }) => {
+                            *density = (*density * factor).clamp(MIN_GAS_DENSITY, MAX_GAS_DENSITY);
+                        }
+                    },
+                    SettingChange::ForceExponent(step) => {
+                        if let ForceLaw::InversePower { exponent, .. } = &mut physics.gravity.law {
+                            *exponent = (*exponent + step).clamp(MIN_EXPONENT, MAX_EXPONENT);
+                        }
+                    }
+                }
+
+                // Refresh the displayed values
+                menu.set_changed();
+            }
+        }
+    }
+}
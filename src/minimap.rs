@@ -0,0 +1,405 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{
+    core_pipeline::tonemapping::{DebandDither, Tonemapping},
+    prelude::*,
+    render::{
+        camera::{ClearColorConfig, ScalingMode, Viewport},
+        view::RenderLayers,
+    },
+    utils::{HashMap, HashSet},
+    window::PrimaryWindow,
+};
+
+use crate::{
+    common::Settings,
+    input::{Action, ActionState},
+    player::{CameraMode, MainCamera, Player, SelectedBody},
+    view::ViewScale,
+    AppState, Planet,
+};
+
+const MINIMAP_LAYER: u8 = 1;
+const MINIMAP_SIZE: f32 = 0.3; // fraction of the window height
+const MINIMAP_MARGIN: f32 = 10.; // pixels
+const MINIMAP_DEPTH: f32 = 1e7; // world units above and below the camera that are drawn
+const MINIMAP_BACKGROUND: Color = Color::rgb(0.02, 0.02, 0.05);
+const DOT_RADIUS: f32 = 3.; // pixels
+/// Dots drawn at once, for the heaviest bodies
+const MAX_DOTS: usize = 1_000;
+const PICK_RADIUS: f32 = 8.; // pixels
+/// Empty space around the bodies, as a fraction of their extent
+const EXTENT_PADDING: f32 = 0.2;
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_gizmo_group::<MinimapGizmos>()
+            .add_systems(Startup, setup_minimap)
+            .add_systems(
+                Update,
+                (
+                    assign_dots,
+                    toggle_minimap,
+                    minimap_click.run_if(in_state(AppState::Simulating)),
+                ),
+            )
+            .add_systems(Last, (fit_minimap, (sync_dots, draw_player_marker)).chain());
+    }
+}
+
+/// Lines only drawn on the minimap
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct MinimapGizmos;
+
+#[derive(Component)]
+struct MinimapCamera;
+
+/// Square behind the dots, scaled to the minimap view
+#[derive(Component)]
+struct MinimapBackground;
+
+/// Marker of a body on the minimap, `Entity::PLACEHOLDER` while unused
+#[derive(Component)]
+struct MinimapDot(Entity);
+
+/// Shared mesh of the dots, a unit disc facing up
+#[derive(Resource)]
+struct DotMesh(Handle<Mesh>);
+
+fn setup_minimap(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut config_store: ResMut<GizmoConfigStore>,
+) {
+    commands
+        .spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    // Drawn on top of the main camera, into the same texture. Clearing would
+                    // wipe the whole texture and not only the viewport, so draw a background instead.
+                    // The texture is only shared when both cameras are HDR.
+                    order: 1,
+                    clear_color: ClearColorConfig::None,
+                    hdr: true,
+                    ..default()
+                },
+                // Post processing runs on the whole texture, it must not touch the main view again
+                tonemapping: Tonemapping::None,
+                dither: DebandDither::Disabled,
+                projection: OrthographicProjection {
+                    near: -MINIMAP_DEPTH,
+                    far: MINIMAP_DEPTH,
+                    ..default()
+                }
+                .into(),
+                transform: Transform::from_rotation(Quat::from_rotation_x(-FRAC_PI_2)),
+                ..default()
+            },
+            RenderLayers::layer(MINIMAP_LAYER),
+            MinimapCamera,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                PbrBundle {
+                    mesh: meshes.add(Rectangle::new(1., 1.)),
+                    material: materials.add(StandardMaterial {
+                        base_color: MINIMAP_BACKGROUND,
+                        unlit: true,
+                        ..default()
+                    }),
+                    // Behind everything the minimap draws
+                    transform: Transform::from_xyz(0., 0., -0.99 * MINIMAP_DEPTH),
+                    ..default()
+                },
+                RenderLayers::layer(MINIMAP_LAYER),
+                MinimapBackground,
+            ));
+        });
+
+    commands.insert_resource(DotMesh(
+        meshes.add(
+            Circle::new(1.)
+                .mesh()
+                .build()
+                .rotated_by(Quat::from_rotation_x(-FRAC_PI_2)),
+        ),
+    ));
+
+    let (config, _) = config_store.config_mut::<MinimapGizmos>();
+    config.render_layers = RenderLayers::layer(MINIMAP_LAYER);
+}
+
+/// Gives the dots to the heaviest bodies, spawning more while there are fewer bodies than
+/// `MAX_DOTS`. A dot keeps its body while it stays among them.
+fn assign_dots(
+    mut commands: Commands,
+    dot_mesh: Res<DotMesh>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    // Dot materials by color, shared like the body materials
+    mut dot_materials: Local<HashMap<[u8; 4], Handle<StandardMaterial>>>,
+    planets_q: Query<(Entity, &Planet, &Handle<StandardMaterial>)>,
+    mut dots_q: Query<(&mut MinimapDot, &mut Handle<StandardMaterial>), Without<Planet>>,
+) {
+    let mut heaviest: Vec<(f32, Entity)> = planets_q
+        .iter()
+        .map(|(entity, planet, _)| (planet.mass(), entity))
+        .collect();
+    if heaviest.len() > MAX_DOTS {
+        heaviest.select_nth_unstable_by(MAX_DOTS - 1, |a, b| b.0.total_cmp(&a.0));
+        heaviest.truncate(MAX_DOTS);
+    }
+
+    let wanted: HashSet<Entity> = heaviest.iter().map(|&(_, entity)| entity).collect();
+    let mut undotted = wanted.clone();
+    for (dot, _) in &dots_q {
+        undotted.remove(&dot.0);
+    }
+    let mut undotted = undotted.into_iter();
+
+    let mut dot_material = |body: Entity| {
+        let color = planets_q
+            .get(body)
+            .ok()
+            .and_then(|(.., material)| materials.get(material))
+            .map_or(Color::WHITE, |material| material.base_color)
+            .with_a(1.);
+        dot_materials
+            .entry(color.as_rgba_u8())
+            .or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: color,
+                    unlit: true,
+                    ..default()
+                })
+            })
+            .clone()
+    };
+
+    for (mut dot, mut material) in &mut dots_q {
+        if wanted.contains(&dot.0) {
+            continue;
+        }
+        dot.0 = undotted.next().unwrap_or(Entity::PLACEHOLDER);
+        *material = dot_material(dot.0);
+    }
+
+    for body in undotted {
+        commands.spawn((
+            PbrBundle {
+                mesh: dot_mesh.0.clone(),
+                material: dot_material(body),
+                ..default()
+            },
+            RenderLayers::layer(MINIMAP_LAYER),
+            MinimapDot(body),
+        ));
+    }
+}
+
+fn toggle_minimap(actions: Res<ActionState>, mut settings: ResMut<Settings>) {
+    if actions.just_pressed(Action::ToggleMinimap) {
+        settings.hud.show_minimap = !settings.hud.show_minimap;
+    }
+}
+
+/// Places the minimap in the bottom right corner and zooms it to fit every body
+fn fit_minimap(
+    settings: Res<Settings>,
+    state: Res<State<AppState>>,
+    view_scale: Res<ViewScale>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    planets_q: Query<&Planet>,
+    mut camera_q: Query<(&mut Camera, &mut Transform, &mut Projection), With<MinimapCamera>>,
+    mut background_q: Query<&mut Transform, (With<MinimapBackground>, Without<MinimapCamera>)>,
+) {
+    let Ok((mut camera, mut camera_transform, mut projection)) = camera_q.get_single_mut() else {
+        return;
+    };
+
+    camera.is_active = settings.hud.show_minimap && *state.get() == AppState::Simulating;
+    if !camera.is_active {
+        return;
+    }
+
+    let window = window_q.single();
+    let size = (window.physical_height() as f32 * MINIMAP_SIZE) as u32;
+    let margin = (MINIMAP_MARGIN * window.scale_factor()) as u32;
+    if size == 0 || window.physical_width() < size + margin {
+        camera.is_active = false;
+        return;
+    }
+    camera.viewport = Some(Viewport {
+        physical_position: UVec2::new(
+            window.physical_width() - size - margin,
+            window.physical_height() - size - margin,
+        ),
+        physical_size: UVec2::splat(size),
+        ..default()
+    });
+
+    // Top-down bounds of the system, on the horizontal plane
+    let (center, extent) = view_scale
+        .bounds(&planets_q)
+        .map_or((Vec2::ZERO, 0.), |(min, max)| {
+            let (min, max) = (min.xz(), max.xz());
+            ((min + max) / 2., ((max - min) / 2.).max_element())
+        });
+    let extent = (extent * (1. + EXTENT_PADDING)).max(1.);
+
+    camera_transform.translation = Vec3::new(center.x, 0., center.y);
+    if let Projection::Orthographic(orthographic) = &mut *projection {
+        orthographic.scaling_mode = ScalingMode::FixedVertical(2. * extent);
+    }
+
+    for mut background_transform in &mut background_q {
+        background_transform.scale = Vec3::new(2. * extent, 2. * extent, 1.);
+    }
+}
+
+/// Dots follow their body and never get smaller than a few pixels
+fn sync_dots(
+    view_scale: Res<ViewScale>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    planets_q: Query<&Planet>,
+    minimap_q: Query<(&Camera, &Projection), With<MinimapCamera>>,
+    mut dots_q: Query<(&MinimapDot, &mut Transform, &mut Visibility)>,
+) {
+    let Ok((camera, Projection::Orthographic(orthographic))) = minimap_q.get_single() else {
+        return;
+    };
+    let (ScalingMode::FixedVertical(height), Some(viewport)) =
+        (orthographic.scaling_mode, &camera.viewport)
+    else {
+        return;
+    };
+    let world_per_pixel =
+        height / viewport.physical_size.y as f32 * window_q.single().scale_factor();
+
+    for (dot, mut transform, mut visibility) in &mut dots_q {
+        let Ok(planet) = planets_q.get(dot.0) else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+        visibility.set_if_neq(Visibility::Inherited);
+
+        transform.translation = view_scale.to_world(planet.position);
+        transform.scale = Vec3::splat(
+            view_scale
+                .radius_to_world(planet.radius)
+                .max(DOT_RADIUS * world_per_pixel),
+        );
+    }
+}
+
+/// Player position and the horizontal field of view of the main camera
+fn draw_player_marker(
+    mut gizmos: Gizmos<MinimapGizmos>,
+    minimap_q: Query<(&Camera, &Projection), With<MinimapCamera>>,
+    camera_q: Query<(&Camera, &Transform, &Projection), With<MainCamera>>,
+) {
+    let Ok((minimap, Projection::Orthographic(orthographic))) = minimap_q.get_single() else {
+        return;
+    };
+    let Ok((camera, camera_transform, Projection::Perspective(perspective))) =
+        camera_q.get_single()
+    else {
+        return;
+    };
+    if !minimap.is_active {
+        return;
+    }
+
+    let ScalingMode::FixedVertical(height) = orthographic.scaling_mode else {
+        return;
+    };
+    let position = camera_transform.translation;
+    gizmos.circle(position, Direction3d::Y, height * 0.015, Color::WHITE);
+
+    let forward = Vec3::new(
+        camera_transform.forward().x,
+        0.,
+        camera_transform.forward().z,
+    );
+    let Some(forward) = forward.try_normalize() else {
+        return;
+    };
+    let aspect = camera
+        .logical_viewport_size()
+        .map_or(1., |size| size.x / size.y);
+    let half_fov = ((perspective.fov / 2.).tan() * aspect).atan();
+    let length = height * 0.15;
+
+    for angle in [-half_fov, half_fov] {
+        let direction = Quat::from_rotation_y(angle) * forward;
+        gizmos.line(position, position + direction * length, Color::YELLOW);
+    }
+}
+
+/// Clicking a dot selects its body, clicking empty space moves the free-fly camera there
+#[allow(clippy::too_many_arguments)]
+fn minimap_click(
+    actions: Res<ActionState>,
+    camera_mode: Res<CameraMode>,
+    view_scale: Res<ViewScale>,
+    mut selected: ResMut<SelectedBody>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    minimap_q: Query<(&Camera, &GlobalTransform), With<MinimapCamera>>,
+    planets_q: Query<(Entity, &Planet)>,
+    mut player_q: Query<(&mut Transform, &mut Player)>,
+) {
+    if !actions.just_pressed(Action::MinimapClick) {
+        return;
+    }
+
+    let Ok((camera, camera_transform)) = minimap_q.get_single() else {
+        return;
+    };
+    let window = window_q.single();
+    let (Some(cursor), Some(viewport)) = (window.cursor_position(), &camera.viewport) else {
+        return;
+    };
+    if !camera.is_active {
+        return;
+    }
+
+    let origin = viewport.physical_position.as_vec2() / window.scale_factor();
+    let cursor = cursor - origin;
+    let Some(size) = camera.logical_viewport_size() else {
+        return;
+    };
+    if cursor.cmplt(Vec2::ZERO).any() || cursor.cmpgt(size).any() {
+        return;
+    }
+
+    let picked = planets_q
+        .iter()
+        .filter_map(|(entity, planet)| {
+            let position =
+                camera.world_to_viewport(camera_transform, view_scale.to_world(planet.position))?;
+            Some((entity, position.distance(cursor)))
+        })
+        .filter(|(_, distance)| *distance <= PICK_RADIUS)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    if let Some((entity, _)) = picked {
+        selected.0 = Some(entity);
+        return;
+    }
+
+    if *camera_mode != CameraMode::FreeFly {
+        return;
+    }
+
+    let Some(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+        return;
+    };
+    let (mut player_transform, mut player) = player_q.single_mut();
+    // Keep the current height, the minimap only shows the horizontal plane
+    player_transform.translation.x = ray.origin.x;
+    player_transform.translation.z = ray.origin.z;
+    player.stop();
+}
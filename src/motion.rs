@@ -0,0 +1,283 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calculate_acceleration_velocity, velocity_verlet, AppState, GameSpeed, Planet, SimStats,
+    TIME_SPEED,
+};
+
+pub struct MotionPlugin;
+
+impl Plugin for MotionPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .add_systems(
+                FixedUpdate,
+                move_kinematic_bodies
+                    .after(velocity_verlet)
+                    .before(calculate_acceleration_velocity)
+                    .run_if(in_state(AppState::Simulating)),
+            );
+    }
+}
+
+/// Body that isn't moved by gravity. It still pulls on the others.
+/// Bodies without it are integrated normally.
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BodyMotion {
+    /// Never moves
+    Static,
+    /// Circle around `center`, counterclockwise seen from the tip of `axis`
+    Circle {
+        center: Vec3,
+        axis: Vec3,
+        /// km
+        radius: f32,
+        /// Seconds per turn
+        period: f32,
+        /// Angle at the start of the scene, in radians
+        #[serde(default)]
+        phase: f32,
+    },
+    /// Closed Catmull-Rom spline through `points`, in km
+    Spline {
+        points: Vec<Vec3>,
+        /// Seconds per loop
+        period: f32,
+        /// Fraction of a loop already done at the start of the scene, in [0, 1)
+        #[serde(default)]
+        offset: f32,
+    },
+}
+
+impl BodyMotion {
+    /// Same path, starting where this one is `time` seconds after the start of the scene.
+    /// Saved scenes restart the clock, this keeps kinematic bodies where they were.
+    pub fn advanced(&self, time: f64) -> BodyMotion {
+        let mut motion = self.clone();
+        match &mut motion {
+            BodyMotion::Static => {}
+            BodyMotion::Circle { period, phase, .. } => {
+                *phase = (*phase + TAU * loop_fraction(time, *period)).rem_euclid(TAU);
+            }
+            BodyMotion::Spline { period, offset, .. } => {
+                *offset = (*offset + loop_fraction(time, *period)).rem_euclid(1.);
+            }
+        }
+        motion
+    }
+
+    /// Position (km) and velocity (km/s) `time` seconds after the start of the scene,
+    /// `None` for static bodies
+    pub fn sample(&self, time: f64) -> Option<(Vec3, Vec3)> {
+        match self {
+            BodyMotion::Static => None,
+            BodyMotion::Circle {
+                center,
+                axis,
+                radius,
+                period,
+                phase,
+            } => {
+                let axis = axis.try_normalize().unwrap_or(Vec3::Y);
+                let start = axis.any_orthonormal_vector();
+                let angle = phase + TAU * loop_fraction(time, *period);
+                let radial = Quat::from_axis_angle(axis, angle) * start;
+                let speed = TAU * radius * loop_rate(*period);
+
+                Some((*center + radial * *radius, axis.cross(radial) * speed))
+            }
+            BodyMotion::Spline {
+                points,
+                period,
+                offset,
+            } => {
+                if points.is_empty() {
+                    return None;
+                }
+
+                // Segment and position along it, each segment takes the same time
+                let count = points.len();
+                let t = (loop_fraction(time, *period) + offset).rem_euclid(1.) * count as f32;
+                let segment = (t as usize).min(count - 1);
+                let local = t - segment as f32;
+                let point = |offset: usize| points[(segment + count + offset - 1) % count];
+                let (p0, p1, p2, p3) = (point(0), point(1), point(2), point(3));
+
+                let position = 0.5
+                    * (2. * p1
+                        + (p2 - p0) * local
+                        + (2. * p0 - 5. * p1 + 4. * p2 - p3) * local.powi(2)
+                        + (3. * p1 - p0 - 3. * p2 + p3) * local.powi(3));
+                let derivative = 0.5
+                    * ((p2 - p0)
+                        + 2. * (2. * p0 - 5. * p1 + 4. * p2 - p3) * local
+                        + 3. * (3. * p1 - p0 - 3. * p2 + p3) * local.powi(2));
+
+                Some((position, derivative * count as f32 * loop_rate(*period)))
+            }
+        }
+    }
+}
+
+/// Fraction of the current loop completed at `time`, in [0, 1)
+fn loop_fraction(time: f64, period: f32) -> f32 {
+    if period <= 0. {
+        return 0.;
+    }
+
+    (time / f64::from(period)).rem_euclid(1.) as f32
+}
+
+/// Loops per second, paths without a positive period stand still
+fn loop_rate(period: f32) -> f32 {
+    if period <= 0. {
+        return 0.;
+    }
+
+    1. / period
+}
+
+/// Moves kinematic bodies along their path, before the forces on the others are computed
+fn move_kinematic_bodies(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    stats: Res<SimStats>,
+    mut planets_q: Query<(&mut Planet, &BodyMotion)>,
+) {
+    // `SimStats::elapsed` only counts this tick once it ends
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+    let now = stats.elapsed + f64::from(dt);
+
+    for (mut planet, motion) in &mut planets_q {
+        let Some((position, velocity)) = motion.sample(now) else {
+            planet.velocity = Vec3::ZERO;
+            continue;
+        };
+
+        planet.position = position;
+        planet.velocity = velocity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle() -> BodyMotion {
+        BodyMotion::Circle {
+            center: Vec3::new(10., 0., -5.),
+            axis: Vec3::Z,
+            radius: 1_000.,
+            period: 400.,
+            phase: 0.3,
+        }
+    }
+
+    fn spline() -> BodyMotion {
+        BodyMotion::Spline {
+            points: vec![
+                Vec3::new(0., 0., 0.),
+                Vec3::new(100., 0., 0.),
+                Vec3::new(100., 100., 0.),
+                Vec3::new(0., 100., 50.),
+            ],
+            period: 80.,
+            offset: 0.1,
+        }
+    }
+
+    fn assert_close(a: Vec3, b: Vec3, tolerance: f32) {
+        assert!(a.distance(b) <= tolerance, "{a} != {b}");
+    }
+
+    #[test]
+    fn static_bodies_have_no_sample() {
+        assert_eq!(BodyMotion::Static.sample(12.), None);
+        assert_eq!(BodyMotion::Static.advanced(12.), BodyMotion::Static);
+    }
+
+    #[test]
+    fn circle_stays_on_its_radius() {
+        let center = Vec3::new(10., 0., -5.);
+        let (start, _) = circle().sample(0.).unwrap();
+
+        for time in [0., 37., 100., 250., 4_000.5] {
+            let (position, velocity) = circle().sample(time).unwrap();
+            let radial = position - center;
+
+            assert!((radial.length() - 1_000.).abs() < 0.1);
+            assert!(radial.dot(Vec3::Z).abs() < 0.1);
+            assert!((velocity.length() - TAU * 1_000. / 400.).abs() < 1e-3);
+            assert!(radial.dot(velocity).abs() < 1.);
+            // Counterclockwise seen from +Z
+            assert!(radial.cross(velocity).z > 0.);
+        }
+
+        // Half a turn later it is on the other side
+        let (half, _) = circle().sample(200.).unwrap();
+        assert_close(half - center, center - start, 0.1);
+    }
+
+    #[test]
+    fn velocity_matches_the_path() {
+        for motion in [circle(), spline()] {
+            for time in [3., 21., 55.] {
+                let (before, _) = motion.sample(time - 0.01).unwrap();
+                let (after, _) = motion.sample(time + 0.01).unwrap();
+                let (_, velocity) = motion.sample(time).unwrap();
+
+                assert_close((after - before) / 0.02, velocity, 0.01 * velocity.length());
+            }
+        }
+    }
+
+    #[test]
+    fn spline_goes_through_its_points() {
+        let BodyMotion::Spline { points, period, .. } = spline() else {
+            unreachable!()
+        };
+        let motion = BodyMotion::Spline {
+            points: points.clone(),
+            period,
+            offset: 0.,
+        };
+
+        for (i, point) in points.iter().enumerate() {
+            let time = f64::from(period) * i as f64 / points.len() as f64;
+            assert_close(motion.sample(time).unwrap().0, *point, 1e-3);
+        }
+    }
+
+    #[test]
+    fn advanced_continues_the_path() {
+        for motion in [circle(), spline()] {
+            let advanced = motion.advanced(1_234.5);
+
+            for time in [0., 10., 333.] {
+                let (position, velocity) = advanced.sample(time).unwrap();
+                let (expected_position, expected_velocity) = motion.sample(1_234.5 + time).unwrap();
+
+                assert_close(position, expected_position, 0.05);
+                assert_close(velocity, expected_velocity, 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn paths_without_a_period_stand_still() {
+        let motion = BodyMotion::Circle {
+            center: Vec3::ZERO,
+            axis: Vec3::Y,
+            radius: 5.,
+            period: 0.,
+            phase: 0.,
+        };
+
+        assert_eq!(motion.sample(0.).unwrap().1, Vec3::ZERO);
+        assert_eq!(motion.sample(0.), motion.sample(99.));
+        assert_eq!(motion.advanced(99.), motion);
+    }
+}
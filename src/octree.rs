@@ -0,0 +1,295 @@
+use std::ops::Range;
+
+use bevy::math::{DVec3, Vec3};
+
+/// Bodies per leaf cell
+const LEAF_SIZE: usize = 8;
+/// Only bodies on top of each other need deeper cells, they stay in one leaf instead
+const MAX_DEPTH: u32 = 24;
+
+/// What the tree needs to know of a body
+#[derive(Debug, Clone, Copy)]
+pub struct TreeBody {
+    /// km
+    pub position: Vec3,
+    /// kg
+    pub mass: f32,
+    /// km
+    pub radius: f32,
+}
+
+/// Part of the tree seen from a body, see [`Octree::visit`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visit {
+    /// Body close enough to be summed exactly, can be the visiting body itself
+    Body(usize),
+    /// Far away cell, pulling like a single body of `mass` at `center_of_mass`
+    Group { mass: f32, center_of_mass: Vec3 },
+}
+
+struct Cell {
+    center: Vec3,
+    half_size: f32,
+    mass: f32,
+    center_of_mass: Vec3,
+    /// Largest body inside, a cell can only be grouped if none of its bodies can touch
+    max_radius: f32,
+    /// First of the 8 children, next to each other. Leaves have none.
+    children: Option<usize>,
+    /// Bodies inside, as a range of `Octree::order`
+    bodies: Range<usize>,
+}
+
+/// Barnes–Hut octree. Far away cells pull like one body at their center of mass, so the
+/// forces on n bodies take about n log n evaluations instead of n².
+pub struct Octree {
+    cells: Vec<Cell>,
+    /// Body indices, the bodies of each cell are contiguous
+    order: Vec<usize>,
+}
+
+impl Octree {
+    pub fn new(bodies: &[TreeBody]) -> Self {
+        let (min, max) = bodies.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), body| (min.min(body.position), max.max(body.position)),
+        );
+        let center = (min + max) / 2.;
+        let half_size = if bodies.is_empty() {
+            1.
+        } else {
+            ((max - min).max_element() / 2.).max(1.)
+        };
+
+        let mut tree = Octree {
+            cells: Vec::new(),
+            order: (0..bodies.len()).collect(),
+        };
+        tree.cells.push(Cell::empty(center, half_size));
+        tree.cells[0] = tree.build(bodies, 0..bodies.len(), center, half_size, 0);
+        tree
+    }
+
+    fn build(
+        &mut self,
+        bodies: &[TreeBody],
+        range: Range<usize>,
+        center: Vec3,
+        half_size: f32,
+        depth: u32,
+    ) -> Cell {
+        let mut cell = Cell::empty(center, half_size);
+        if range.is_empty() {
+            return cell;
+        }
+
+        // f64, star masses times km overflow f32
+        let (mass, weighted) =
+            self.order[range.clone()]
+                .iter()
+                .fold((0_f64, DVec3::ZERO), |(mass, weighted), &i| {
+                    let m = f64::from(bodies[i].mass);
+                    (mass + m, weighted + bodies[i].position.as_dvec3() * m)
+                });
+        cell.mass = mass as f32;
+        cell.center_of_mass = if mass > 0. {
+            (weighted / mass).as_vec3()
+        } else {
+            center
+        };
+        cell.max_radius = self.order[range.clone()]
+            .iter()
+            .map(|&i| bodies[i].radius)
+            .fold(0., f32::max);
+        cell.bodies = range.clone();
+
+        if range.len() <= LEAF_SIZE || depth >= MAX_DEPTH {
+            return cell;
+        }
+
+        // Group the bodies by octant, bit 0 for +X, 1 for +Y and 2 for +Z
+        let octant = |position: Vec3| {
+            usize::from(position.x >= center.x)
+                | usize::from(position.y >= center.y) << 1
+                | usize::from(position.z >= center.z) << 2
+        };
+        self.order[range.clone()].sort_unstable_by_key(|&i| octant(bodies[i].position));
+
+        let first = self.cells.len();
+        self.cells
+            .extend((0..8).map(|_| Cell::empty(center, half_size / 2.)));
+        let mut start = range.start;
+        for child in 0..8 {
+            let end = start
+                + self.order[start..range.end]
+                    .iter()
+                    .take_while(|&&i| octant(bodies[i].position) == child)
+                    .count();
+            let offset = Vec3::new(
+                if child & 1 != 0 { 1. } else { -1. },
+                if child & 2 != 0 { 1. } else { -1. },
+                if child & 4 != 0 { 1. } else { -1. },
+            ) * (half_size / 2.);
+            self.cells[first + child] = self.build(
+                bodies,
+                start..end,
+                center + offset,
+                half_size / 2.,
+                depth + 1,
+            );
+            start = end;
+        }
+        cell.children = Some(first);
+
+        cell
+    }
+
+    /// Walks the tree from the body at `index`. Cells narrower than `theta` times their
+    /// distance, that none of their bodies can touch, are visited as a [`Visit::Group`],
+    /// the bodies of the others one by one.
+    pub fn visit(
+        &self,
+        bodies: &[TreeBody],
+        index: usize,
+        theta: f32,
+        mut visitor: impl FnMut(Visit),
+    ) {
+        let body = bodies[index];
+        let mut stack = vec![0];
+
+        while let Some(cell) = stack.pop() {
+            let cell = &self.cells[cell];
+            if cell.bodies.is_empty() {
+                continue;
+            }
+
+            // Distance from the body to the box of the cell, zero inside it
+            let gap = ((body.position - cell.center).abs() - cell.half_size)
+                .max(Vec3::ZERO)
+                .length();
+            let distance = body.position.distance(cell.center_of_mass);
+            if gap > body.radius + cell.max_radius && 2. * cell.half_size < theta * distance {
+                visitor(Visit::Group {
+                    mass: cell.mass,
+                    center_of_mass: cell.center_of_mass,
+                });
+                continue;
+            }
+
+            match cell.children {
+                Some(first) => stack.extend(first..first + 8),
+                None => {
+                    for &i in &self.order[cell.bodies.clone()] {
+                        visitor(Visit::Body(i));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Cell {
+    fn empty(center: Vec3, half_size: f32) -> Self {
+        Cell {
+            center,
+            half_size,
+            mass: 0.,
+            center_of_mass: center,
+            max_radius: 0.,
+            children: None,
+            bodies: 0..0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bodies spread over a cube, from a fixed seed
+    fn scattered(count: usize) -> Vec<TreeBody> {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % 1_000_000) as f32 / 1_000_000.
+        };
+        (0..count)
+            .map(|_| TreeBody {
+                position: Vec3::new(next(), next(), next()) * 200_000. - 100_000.,
+                mass: 1e20 + next() * 1e22,
+                radius: 100.,
+            })
+            .collect()
+    }
+
+    fn pull(from: Vec3, to: Vec3, mass: f32) -> Vec3 {
+        let offset = to - from;
+        offset * (mass / offset.length().powi(3))
+    }
+
+    #[test]
+    fn groups_approximate_the_exact_sum() {
+        let bodies = scattered(2_000);
+        let tree = Octree::new(&bodies);
+
+        for index in (0..bodies.len()).step_by(97) {
+            let position = bodies[index].position;
+            let mut approximate = Vec3::ZERO;
+            let mut groups = 0;
+            tree.visit(&bodies, index, 0.7, |visit| match visit {
+                Visit::Body(j) if j == index => {}
+                Visit::Body(j) => approximate += pull(position, bodies[j].position, bodies[j].mass),
+                Visit::Group {
+                    mass,
+                    center_of_mass,
+                } => {
+                    groups += 1;
+                    approximate += pull(position, center_of_mass, mass);
+                }
+            });
+            let exact = (0..bodies.len())
+                .filter(|&j| j != index)
+                .fold(Vec3::ZERO, |sum, j| {
+                    sum + pull(position, bodies[j].position, bodies[j].mass)
+                });
+
+            assert!(groups > 0);
+            assert!((approximate - exact).length() < 0.05 * exact.length());
+        }
+    }
+
+    #[test]
+    fn every_body_is_visited_once() {
+        let bodies = scattered(500);
+        let tree = Octree::new(&bodies);
+
+        // Theta 0 never groups
+        let mut seen = vec![0; bodies.len()];
+        tree.visit(&bodies, 0, 0., |visit| match visit {
+            Visit::Body(j) => seen[j] += 1,
+            Visit::Group { .. } => panic!("grouped with theta 0"),
+        });
+        assert!(seen.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn touching_bodies_are_never_grouped() {
+        let mut bodies = scattered(500);
+        // A large body reaching the far corner of the cube
+        bodies.push(TreeBody {
+            position: Vec3::splat(-100_000.),
+            mass: 1e24,
+            radius: 400_000.,
+        });
+        let tree = Octree::new(&bodies);
+
+        let mut visited = false;
+        tree.visit(&bodies, 0, 10., |visit| match visit {
+            Visit::Body(j) => visited |= j == bodies.len() - 1,
+            Visit::Group { .. } => {}
+        });
+        assert!(visited);
+    }
+}
@@ -0,0 +1,296 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::{
+    prelude::*,
+    render::{mesh::PrimitiveTopology, render_asset::RenderAssetUsages, view::NoFrustumCulling},
+    tasks::{ComputeTaskPool, ParallelSliceMut},
+    transform::TransformSystem,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calculate_acceleration_velocity, end_physics_tick, forces::Gravity, scenes::BodyDescription,
+    utils::KG_PER_KM3_PER_G_CM3, view::ViewScale, AppState, GameSpeed, PhysicsSettings, Planet,
+    TIME_SPEED,
+};
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<TestParticles>()
+            .add_systems(Startup, setup_particle_cloud)
+            .add_systems(
+                FixedUpdate,
+                integrate_particles
+                    .after(calculate_acceleration_velocity)
+                    .before(end_physics_tick)
+                    .run_if(in_state(AppState::Simulating)),
+            )
+            .add_systems(
+                PostUpdate,
+                update_particle_cloud.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Massless body, pulled by the planets without pulling on anything
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    /// km
+    pub position: Vec3,
+    /// km/s
+    pub velocity: Vec3,
+    /// km/s²
+    acceleration: Vec3,
+    color: [f32; 4],
+    /// Hit a planet, removed at the end of the tick
+    absorbed: bool,
+}
+
+/// Every test particle of the scene. Kept out of the ECS, there can be hundreds of thousands
+/// and they are all drawn as a single point cloud.
+#[derive(Resource, Default)]
+pub struct TestParticles(pub Vec<Particle>);
+
+impl TestParticles {
+    /// Current particles as scene file clouds, one per run of particles of the same color
+    pub fn to_clouds(&self) -> Vec<ParticleCloud> {
+        self.0
+            .chunk_by(|a, b| a.color == b.color)
+            .map(|run| ParticleCloud::Points {
+                positions: run.iter().map(|particle| particle.position).collect(),
+                velocities: run.iter().map(|particle| particle.velocity).collect(),
+                color: Color::rgba_linear_from_array(run[0].color),
+            })
+            .collect()
+    }
+}
+
+/// Test particles of a scene file, generated when the scene loads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ParticleCloud {
+    /// Particles on circular orbits in the equatorial plane of the body named `around`
+    Ring {
+        around: String,
+        /// km
+        inner_radius: f32,
+        /// km
+        outer_radius: f32,
+        count: usize,
+        /// Height of the ring, in km
+        #[serde(default)]
+        thickness: f32,
+        color: Color,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// Explicit particles, how saved scenes store them
+    Points {
+        positions: Vec<Vec3>,
+        velocities: Vec<Vec3>,
+        color: Color,
+    },
+}
+
+impl ParticleCloud {
+    /// Particles of the cloud, rings orbit under the `gravity` of the scene
+    pub fn generate(&self, bodies: &[BodyDescription], gravity: &Gravity) -> Vec<Particle> {
+        let particle = |position: Vec3, velocity: Vec3, color: Color| Particle {
+            position,
+            velocity,
+            acceleration: Vec3::ZERO,
+            color: color.as_linear_rgba_f32(),
+            absorbed: false,
+        };
+
+        match self {
+            ParticleCloud::Ring {
+                around,
+                inner_radius,
+                outer_radius,
+                count,
+                thickness,
+                color,
+                seed,
+            } => {
+                let Some(body) = bodies.iter().find(|body| body.name == *around) else {
+                    warn!("No body named {around} for a particle ring");
+                    return Vec::new();
+                };
+                // Scene files can hold anything, a zero radius gives infinite speeds
+                if !(*inner_radius > 0. && inner_radius <= outer_radius && outer_radius.is_finite())
+                {
+                    warn!(
+                        "Invalid particle ring around {around}, radii {inner_radius} to \
+                         {outer_radius} km, skipping it"
+                    );
+                    return Vec::new();
+                }
+                let mass =
+                    (4. / 3.) * PI * body.radius.powi(3) * body.density * KG_PER_KM3_PER_G_CM3;
+                let normal = body.orientation * Vec3::Y;
+                let start = normal.any_orthonormal_vector();
+
+                let mut rng = StdRng::seed_from_u64(*seed);
+                (0..*count)
+                    .map(|_| {
+                        // Uniform over the area of the ring
+                        let radius = rng
+                            .gen_range(inner_radius.powi(2)..=outer_radius.powi(2))
+                            .sqrt();
+                        let radial = Quat::from_axis_angle(normal, rng.gen_range(0. ..TAU)) * start;
+                        let height = rng.gen_range(-0.5..=0.5) * thickness;
+                        let speed = gravity.circular_speed(mass, radius);
+
+                        particle(
+                            body.position + radial * radius + normal * height,
+                            body.velocity + normal.cross(radial) * speed,
+                            *color,
+                        )
+                    })
+                    .collect()
+            }
+            ParticleCloud::Points {
+                positions,
+                velocities,
+                color,
+            } => {
+                if positions.len() != velocities.len() {
+                    warn!(
+                        "Particle cloud with {} positions and {} velocities, skipping it",
+                        positions.len(),
+                        velocities.len()
+                    );
+                    return Vec::new();
+                }
+
+                positions
+                    .iter()
+                    .zip(velocities)
+                    .map(|(&position, &velocity)| particle(position, velocity, *color))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Mesh of every particle, one vertex each
+#[derive(Component)]
+struct ParticleCloudMesh;
+
+fn setup_particle_cloud(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // Filled every frame, a vertex buffer can't be empty
+    let mesh = Mesh::new(PrimitiveTopology::PointList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0., 0., 0.]])
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, vec![[1., 1., 1., 1.]]);
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(mesh),
+            material: materials.add(StandardMaterial {
+                base_color: Color::WHITE,
+                unlit: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        // The bounds change every frame
+        NoFrustumCulling,
+        ParticleCloudMesh,
+    ));
+}
+
+/// Velocity Verlet against the gravity of the planets only, the planets already moved this
+/// tick. Particles carry no charge. Particles that hit a planet are absorbed.
+fn integrate_particles(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    physics: Res<PhysicsSettings>,
+    mut particles: ResMut<TestParticles>,
+    planets_q: Query<&Planet>,
+) {
+    if particles.0.is_empty() {
+        return;
+    }
+
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+    let gravity = physics.gravity;
+    let bodies: Vec<(Vec3, f32, f32)> = planets_q
+        .iter()
+        .map(|planet| (planet.position, planet.radius, planet.mass()))
+        .collect();
+
+    let absorbed = particles
+        .0
+        .par_splat_map_mut(ComputeTaskPool::get(), None, |chunk| {
+            let mut absorbed = false;
+            for particle in chunk {
+                particle.position +=
+                    particle.velocity * dt + particle.acceleration * (dt * dt * 0.5);
+
+                let mut acceleration = Vec3::ZERO;
+                for &(position, radius, mass) in &bodies {
+                    if particle.position.distance_squared(position) < radius * radius {
+                        particle.absorbed = true;
+                        absorbed = true;
+                        break;
+                    }
+                    acceleration += gravity.acceleration(particle.position, position, mass);
+                }
+                if particle.absorbed {
+                    continue;
+                }
+
+                particle.velocity += (particle.acceleration + acceleration) * (dt * 0.5);
+                particle.acceleration = acceleration;
+            }
+            absorbed
+        })
+        .into_iter()
+        .any(|absorbed| absorbed);
+
+    if absorbed {
+        particles.0.retain(|particle| !particle.absorbed);
+    }
+}
+
+fn update_particle_cloud(
+    particles: Res<TestParticles>,
+    view_scale: Res<ViewScale>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut cloud_q: Query<(&Handle<Mesh>, &mut Visibility), With<ParticleCloudMesh>>,
+) {
+    let Ok((mesh, mut visibility)) = cloud_q.get_single_mut() else {
+        return;
+    };
+
+    let show = !particles.0.is_empty();
+    if (*visibility == Visibility::Hidden) == show {
+        *visibility = if show {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    let Some(mesh) = meshes.get_mut(mesh).filter(|_| show) else {
+        return;
+    };
+
+    let positions: Vec<[f32; 3]> = particles
+        .0
+        .iter()
+        .map(|particle| view_scale.to_world(particle.position).to_array())
+        .collect();
+    let colors: Vec<[f32; 4]> = particles.0.iter().map(|particle| particle.color).collect();
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
@@ -1,19 +1,50 @@
 use std::f32::consts::PI;
 
 use bevy::{
+    core_pipeline::bloom::BloomSettings,
     ecs::query::QuerySingleError,
-    input::mouse::MouseMotion,
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    math::DVec3,
     prelude::*,
     window::{CursorGrabMode, PrimaryWindow},
 };
 
-const MOUSE_SENSITIVITY: f32 = 0.65;
+use crate::{
+    common::Settings,
+    hud::CursorOverHud,
+    input::{Action, ActionState},
+    view::ViewScale,
+    AppState, Planet,
+};
+
+pub const MOUSE_SENSITIVITY: f32 = 0.65;
+const GAMEPAD_LOOK_SPEED: f32 = 2.; // radians per second at full stick and sensitivity 1
+const GAMEPAD_ZOOM_SPEED: f32 = 5.; // scroll lines per second
+
+// Movement slows down to this fraction of `base_speed` next to a body
+const MIN_SPEED_FACTOR: f32 = 0.02;
+const MAX_SPEED_FACTOR: f32 = 4.;
+// World distance to the nearest surface at which `base_speed` applies
+const SPEED_REFERENCE_DISTANCE: f32 = 300.;
+const ORBIT_SMOOTHING: f32 = 6.; // per second, higher is snappier
+const ORBIT_MIN_DISTANCE: f32 = 1.;
+const ORBIT_MAX_DISTANCE: f32 = 5000.;
 
 pub struct PlayerPlugin;
 
 #[derive(Component)]
 pub struct Player {
     base_speed: f32,
+    velocity: Vec3,
+    roll_velocity: f32,
+}
+
+impl Player {
+    /// Drops any leftover movement, after the player was moved from outside
+    pub fn stop(&mut self) {
+        self.velocity = Vec3::ZERO;
+        self.roll_velocity = 0.;
+    }
 }
 
 #[derive(Bundle)]
@@ -25,33 +56,146 @@ struct PlayerBundle {
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app //
+            .init_resource::<CameraMode>()
+            .init_resource::<CameraSettings>()
+            .init_resource::<SelectedBody>()
+            .init_resource::<OrbitCamera>()
             .add_systems(Startup, (setup_camera, setup_player))
-            .add_systems(Update, (mouse_motion, move_player, cursor_grab))
-            .add_systems(Last, camera_follow_player);
+            .add_systems(
+                Update,
+                (
+                    (
+                        move_player.run_if(resource_equals(CameraMode::FreeFly)),
+                        mouse_motion.run_if(
+                            resource_equals(CameraMode::FreeFly)
+                                .or_else(resource_equals(CameraMode::Ship)),
+                        ),
+                        orbit_input.run_if(resource_equals(CameraMode::Orbit)),
+                        cursor_grab,
+                        cycle_selected_body,
+                        toggle_camera_mode,
+                    )
+                        .run_if(in_state(AppState::Simulating)),
+                    apply_settings.run_if(resource_changed::<Settings>),
+                ),
+            )
+            .add_systems(
+                Last,
+                (
+                    camera_follow_player.run_if(resource_equals(CameraMode::FreeFly)),
+                    orbit_camera.run_if(resource_equals(CameraMode::Orbit)),
+                ),
+            );
     }
 }
 
 #[derive(Component)]
-struct MainCamera;
+pub struct MainCamera;
+
+#[derive(Resource, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum CameraMode {
+    #[default]
+    FreeFly,
+    Orbit,
+    /// Flying a ship body, see [`crate::ship`]
+    Ship,
+}
+
+/// Tuning of the free-fly controller
+#[derive(Resource)]
+pub struct CameraSettings {
+    pub mouse_sensitivity: f32,
+    pub invert_y: bool,
+    /// How fast the camera reaches the requested speed, per second
+    pub acceleration: f32,
+    /// How fast the camera stops when there's no input, per second
+    pub damping: f32,
+    /// Radians per second
+    pub roll_speed: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            mouse_sensitivity: MOUSE_SENSITIVITY,
+            invert_y: false,
+            acceleration: 4.,
+            damping: 2.,
+            roll_speed: 1.5,
+        }
+    }
+}
+
+/// Body the camera orbits around, the system barycenter when `None`
+#[derive(Resource, Default)]
+pub struct SelectedBody(pub Option<Entity>);
+
+#[derive(Resource)]
+struct OrbitCamera {
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    // Smoothed values actually used for the camera
+    focus: Vec3,
+    current_distance: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        OrbitCamera {
+            yaw: 0.,
+            pitch: 0.,
+            distance: 500.,
+            focus: Vec3::ZERO,
+            current_distance: 500.,
+        }
+    }
+}
 
 fn setup_camera(mut commands: Commands) {
     commands
-        .spawn(Camera3dBundle {
-            transform: Transform::from_xyz(0.0, 0.0, 1.0).looking_at(Vec3::ZERO, Vec3::Y),
-            ..default()
-        })
-        .insert(MainCamera);
+        .spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    // Stars are brighter than white, HDR keeps that for the bloom
+                    hdr: true,
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, 1.0).looking_at(Vec3::ZERO, Vec3::Y),
+                ..default()
+            },
+            BloomSettings::NATURAL,
+        ))
+        // The minimap camera is drawn later, the UI stays on this one
+        .insert((MainCamera, IsDefaultUiCamera));
 }
 
-fn setup_player(mut commands: Commands) {
+fn setup_player(mut commands: Commands, settings: Res<Settings>) {
     commands
         .spawn(PlayerBundle {
-            player: Player { base_speed: 500. },
+            player: Player {
+                base_speed: settings.base_speed,
+                velocity: Vec3::ZERO,
+                roll_velocity: 0.,
+            },
             // transform: Transform::from_xyz(0., 0., 750.),
         })
         .insert(Transform::from_xyz(0., -250., 750.));
 }
 
+fn apply_settings(
+    settings: Res<Settings>,
+    mut camera_settings: ResMut<CameraSettings>,
+    mut player_q: Query<&mut Player>,
+) {
+    camera_settings.mouse_sensitivity = settings.mouse_sensitivity;
+    camera_settings.invert_y = settings.invert_y;
+
+    for mut player in &mut player_q {
+        player.base_speed = settings.base_speed;
+    }
+}
+
 fn camera_follow_player(
     player_q: Query<&Transform, (With<Player>, Without<MainCamera>)>,
     mut camera_q: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
@@ -69,67 +213,85 @@ fn camera_follow_player(
     }
 }
 
+/// Frame rate independent factor to move a value towards its target at `rate` per second
+fn smoothing(rate: f32, dt: f32) -> f32 {
+    1. - (-rate * dt).exp()
+}
+
 fn move_player(
     time: Res<Time>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut player_q: Query<(&mut Transform, &Player)>,
-    camera_q: Query<&Transform, (With<MainCamera>, Without<Player>)>,
+    actions: Res<ActionState>,
+    settings: Res<CameraSettings>,
+    mut player_q: Query<(&mut Transform, &mut Player)>,
+    mut camera_q: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
+    planets_q: Query<(&GlobalTransform, &Planet)>,
 ) {
-    let (mut player_transform, player) = player_q.single_mut();
-    let camera_transform = camera_q.single();
+    let (mut player_transform, mut player) = player_q.single_mut();
+    let mut camera_transform = camera_q.single_mut();
+    let dt = time.delta_seconds();
+
+    // Analog sticks give partial movement, so only clamp instead of normalizing
+    let movement = (*camera_transform.forward()
+        * actions.axis(Action::MoveBack, Action::MoveForward)
+        + *camera_transform.right() * actions.axis(Action::MoveLeft, Action::MoveRight)
+        + *camera_transform.up() * actions.axis(Action::MoveDown, Action::MoveUp))
+    .clamp_length_max(1.);
+
+    // Slow down close to bodies, speed up in empty space
+    let nearest_surface = planets_q
+        .iter()
+        .map(|(transform, _)| {
+            player_transform
+                .translation
+                .distance(transform.translation())
+                - transform.compute_transform().scale.x
+        })
+        .reduce(f32::min);
+    let distance_factor = nearest_surface.map_or(1., |distance| {
+        (distance / SPEED_REFERENCE_DISTANCE).clamp(MIN_SPEED_FACTOR, MAX_SPEED_FACTOR)
+    });
 
-    let mut speed = player.base_speed;
-    let mut movement = Vec3::ZERO;
-    let local_z = camera_transform.local_z();
-    let forward = -Vec3::new(local_z.x, 0., local_z.z).normalize_or_zero();
-    let right = Vec3::new(local_z.z, 0., -local_z.x).normalize_or_zero();
+    let mut speed = player.base_speed * distance_factor;
 
-    if keyboard_input.pressed(KeyCode::KeyW) {
-        movement += forward;
+    if actions.pressed(Action::Boost) {
+        speed *= 2.;
     }
 
-    if keyboard_input.pressed(KeyCode::KeyS) {
-        movement += -forward;
-    }
+    let target_velocity = movement * speed;
+    let rate = if movement == Vec3::ZERO {
+        settings.damping
+    } else {
+        settings.acceleration
+    };
+    player.velocity = player.velocity.lerp(target_velocity, smoothing(rate, dt));
 
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        movement += right;
-    }
+    player_transform.translation += player.velocity * dt;
 
-    if keyboard_input.pressed(KeyCode::KeyA) {
-        movement += -right;
-    }
+    // Roll
+    let roll = actions.axis(Action::RollRight, Action::RollLeft);
 
-    if keyboard_input.pressed(KeyCode::Space) {
-        // movement += camera_transform.up() * 1.;
-        movement += Vec3::Y;
-    }
+    let target_roll = roll * settings.roll_speed;
+    let rate = if roll == 0. {
+        settings.damping
+    } else {
+        settings.acceleration
+    };
+    player.roll_velocity += (target_roll - player.roll_velocity) * smoothing(rate, dt);
 
-    if keyboard_input.pressed(KeyCode::ControlLeft) {
-        // movement += camera_transform.down() * 1.;
-        movement -= Vec3::Y;
-    }
-
-    if keyboard_input.pressed(KeyCode::ShiftLeft) {
-        speed *= 2.;
-    }
-
-    player_transform.translation += movement.normalize_or_zero() * speed * time.delta_seconds();
+    camera_transform.rotate_local_z(player.roll_velocity * dt);
 
     // println!("Player pos: {}", player_transform.translation);
 }
 
-fn mouse_motion(
-    mut motion_evr: EventReader<MouseMotion>,
-    mut camera_q: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-) {
-    let primary_window = window_query.single();
-
-    if primary_window.cursor.grab_mode != CursorGrabMode::Locked {
-        return;
-    }
-
+/// Mouse (while grabbed) and gamepad look input this frame, in radians
+fn look_input(
+    motion_evr: &mut EventReader<MouseMotion>,
+    primary_window: &Window,
+    mouse_active: bool,
+    actions: &ActionState,
+    settings: &CameraSettings,
+    dt: f32,
+) -> Vec2 {
     let mut rotation_move = Vec2::ZERO;
 
     for ev in motion_evr.read() {
@@ -137,28 +299,63 @@ fn mouse_motion(
         rotation_move += ev.delta;
     }
 
-    if rotation_move.length_squared() > 0.0 {
-        let mut camera_transform = camera_q.single_mut();
-        // Using smallest of height or width ensures equal vertical and horizontal sensitivity
-        let window_scale = primary_window.height().min(primary_window.width());
+    if !mouse_active {
+        rotation_move = Vec2::ZERO;
+    }
 
-        let (mut yaw, mut pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
+    // Using smallest of height or width ensures equal vertical and horizontal sensitivity
+    let window_scale = primary_window.height().min(primary_window.width());
+    let mut look = rotation_move / window_scale * PI;
 
-        yaw -= rotation_move.x / window_scale * PI * MOUSE_SENSITIVITY;
-        pitch -= rotation_move.y / window_scale * PI * MOUSE_SENSITIVITY;
+    let stick = Vec2::new(
+        actions.axis(Action::LookLeft, Action::LookRight),
+        actions.axis(Action::LookUp, Action::LookDown),
+    );
+    look += stick * GAMEPAD_LOOK_SPEED * dt;
 
-        pitch = pitch.clamp(-1.56, 1.56); // PI / 2.
+    look *= settings.mouse_sensitivity;
 
-        camera_transform.rotation =
-            Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch);
+    if settings.invert_y {
+        look.y = -look.y;
     }
+
+    look
 }
 
-fn cursor_grab(
-    mut q_windows: Query<&mut Window, With<PrimaryWindow>>,
-    mouse_buttons: Res<ButtonInput<MouseButton>>,
+fn mouse_motion(
+    time: Res<Time>,
+    mut motion_evr: EventReader<MouseMotion>,
+    actions: Res<ActionState>,
+    settings: Res<CameraSettings>,
+    mut camera_q: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
 ) {
-    if mouse_buttons.just_pressed(MouseButton::Right) {
+    let primary_window = window_query.single();
+    let grabbed = primary_window.cursor.grab_mode == CursorGrabMode::Locked;
+
+    let look = look_input(
+        &mut motion_evr,
+        primary_window,
+        grabbed,
+        &actions,
+        &settings,
+        time.delta_seconds(),
+    );
+
+    if look.length_squared() > 0.0 {
+        let mut camera_transform = camera_q.single_mut();
+
+        let yaw = -look.x;
+        let pitch = -look.y;
+
+        // Rotate around the camera's own axes so it keeps working upside down and rolled
+        camera_transform.rotate_local_y(yaw);
+        camera_transform.rotate_local_x(pitch);
+    }
+}
+
+fn cursor_grab(mut q_windows: Query<&mut Window, With<PrimaryWindow>>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::ToggleCursorGrab) {
         let mut primary_window = q_windows.single_mut();
 
         match primary_window.cursor.grab_mode {
@@ -177,3 +374,158 @@ fn cursor_grab(
         }
     }
 }
+
+fn cycle_selected_body(
+    actions: Res<ActionState>,
+    mut selected: ResMut<SelectedBody>,
+    planets_q: Query<Entity, With<Planet>>,
+) {
+    if !actions.just_pressed(Action::CycleTarget) {
+        return;
+    }
+
+    let mut planets: Vec<Entity> = planets_q.iter().collect();
+    planets.sort();
+
+    // Barycenter -> first planet -> ... -> last planet -> barycenter
+    selected.0 = match selected.0 {
+        None => planets.first().copied(),
+        Some(current) => planets
+            .iter()
+            .position(|&planet| planet == current)
+            .and_then(|i| planets.get(i + 1).copied()),
+    };
+}
+
+fn toggle_camera_mode(
+    actions: Res<ActionState>,
+    mut camera_mode: ResMut<CameraMode>,
+    mut orbit: ResMut<OrbitCamera>,
+    mut player_q: Query<(&mut Transform, &mut Player), Without<MainCamera>>,
+    camera_q: Query<&Transform, (With<MainCamera>, Without<Player>)>,
+) {
+    if !actions.just_pressed(Action::ToggleCameraMode) {
+        return;
+    }
+
+    let camera_transform = camera_q.single();
+
+    match *camera_mode {
+        CameraMode::FreeFly => {
+            // Start orbiting from where the camera is, looking at the same point
+            let (yaw, pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
+            orbit.yaw = yaw;
+            orbit.pitch = pitch;
+            orbit.focus =
+                camera_transform.translation + camera_transform.forward() * orbit.distance;
+            orbit.current_distance = orbit.distance;
+
+            *camera_mode = CameraMode::Orbit;
+        }
+        CameraMode::Orbit => {
+            // Keep flying from the current orbit position
+            let (mut player_transform, mut player) = player_q.single_mut();
+            player_transform.translation = camera_transform.translation;
+            player.stop();
+
+            *camera_mode = CameraMode::FreeFly;
+        }
+        // The ship has its own toggle
+        CameraMode::Ship => {}
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn orbit_input(
+    time: Res<Time>,
+    mut motion_evr: EventReader<MouseMotion>,
+    mut wheel_evr: EventReader<MouseWheel>,
+    actions: Res<ActionState>,
+    settings: Res<CameraSettings>,
+    cursor_over_hud: Res<CursorOverHud>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut orbit: ResMut<OrbitCamera>,
+) {
+    let primary_window = window_query.single();
+
+    // Drag with the middle button, or just move the mouse while grabbed
+    let dragging = actions.pressed(Action::OrbitDrag)
+        || primary_window.cursor.grab_mode == CursorGrabMode::Locked;
+
+    let look = look_input(
+        &mut motion_evr,
+        primary_window,
+        dragging,
+        &actions,
+        &settings,
+        time.delta_seconds(),
+    );
+
+    orbit.yaw -= look.x;
+    orbit.pitch -= look.y;
+    orbit.pitch = orbit.pitch.clamp(-1.56, 1.56); // PI / 2.
+
+    let gamepad_zoom =
+        actions.axis(Action::ZoomOut, Action::ZoomIn) * GAMEPAD_ZOOM_SPEED * time.delta_seconds();
+
+    // The event log scrolls instead
+    let wheel_zoom = wheel_evr
+        .read()
+        .filter(|_| !cursor_over_hud.0)
+        .map(|ev| match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / 100.,
+        });
+
+    for scroll in wheel_zoom.chain([gamepad_zoom]) {
+        orbit.distance =
+            (orbit.distance * 0.9f32.powf(scroll)).clamp(ORBIT_MIN_DISTANCE, ORBIT_MAX_DISTANCE);
+    }
+}
+
+fn orbit_camera(
+    time: Res<Time>,
+    view_scale: Res<ViewScale>,
+    selected: Res<SelectedBody>,
+    mut orbit: ResMut<OrbitCamera>,
+    planets_q: Query<(&Transform, &Planet), Without<MainCamera>>,
+    mut camera_q: Query<&mut Transform, (With<MainCamera>, Without<Planet>)>,
+) {
+    let (target, target_radius) = match selected.0.and_then(|entity| planets_q.get(entity).ok()) {
+        Some((transform, _)) => (transform.translation, transform.scale.x),
+        None => {
+            // System barycenter, f64 since star masses times km overflow f32
+            let (weighted, total_mass) =
+                planets_q
+                    .iter()
+                    .fold((DVec3::ZERO, 0.), |(weighted, total_mass), (_, planet)| {
+                        let mass = f64::from(planet.mass());
+                        (
+                            weighted + planet.position.as_dvec3() * mass,
+                            total_mass + mass,
+                        )
+                    });
+            let barycenter = if total_mass > 0. {
+                (weighted / total_mass).as_vec3()
+            } else {
+                Vec3::ZERO
+            };
+
+            (view_scale.to_world(barycenter), 0.)
+        }
+    };
+
+    // Don't zoom into the target
+    let min_distance = (target_radius * 1.5).max(ORBIT_MIN_DISTANCE);
+    orbit.distance = orbit.distance.max(min_distance);
+
+    let smoothing = smoothing(ORBIT_SMOOTHING, time.delta_seconds());
+    orbit.focus = orbit.focus.lerp(target, smoothing);
+    orbit.current_distance += (orbit.distance - orbit.current_distance) * smoothing;
+
+    let mut camera_transform = camera_q.single_mut();
+    camera_transform.rotation =
+        Quat::from_axis_angle(Vec3::Y, orbit.yaw) * Quat::from_axis_angle(Vec3::X, orbit.pitch);
+    camera_transform.translation =
+        orbit.focus + camera_transform.rotation * Vec3::Z * orbit.current_distance;
+}
@@ -0,0 +1,295 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+
+use crate::{
+    calculate_acceleration_velocity, end_physics_tick, player::SelectedBody, AppState, GameSpeed,
+    PhysicsSettings, Planet, TIME_SPEED,
+};
+
+pub struct RelativityPlugin;
+
+impl Plugin for RelativityPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<Precession>()
+            .add_systems(OnEnter(AppState::Loading), reset_precession)
+            .add_systems(
+                FixedUpdate,
+                track_precession
+                    .after(calculate_acceleration_velocity)
+                    .before(end_physics_tick)
+                    .run_if(in_state(AppState::Simulating))
+                    .run_if(|physics: Res<PhysicsSettings>| physics.post_newtonian),
+            );
+    }
+}
+
+/// State of a body for the post-Newtonian terms
+pub struct PnBody {
+    /// km
+    pub position: Vec3,
+    /// km/s
+    pub velocity: Vec3,
+    /// G m, in km³/s²
+    pub gm: f32,
+    /// km
+    pub radius: f32,
+    /// Newtonian acceleration, km/s²
+    pub acceleration: Vec3,
+}
+
+/// First post-Newtonian (Einstein-Infeld-Hoffmann) acceleration of each body, on top of the
+/// Newtonian one. Touching bodies don't interact, like in the Newtonian loop.
+/// The terms are around (v / c)² of the Newtonian acceleration, below f32 precision for
+/// real speeds of light, so scenes scale `speed_of_light` down to see them.
+pub fn post_newtonian_corrections(bodies: &[PnBody], speed_of_light: f32) -> Vec<Vec3> {
+    let c2 = speed_of_light * speed_of_light;
+    let touching = |a: &PnBody, b: &PnBody| {
+        a.position.distance_squared(b.position) < (a.radius + b.radius).powi(2)
+    };
+
+    // Newtonian potential at each body, G m / r summed over the others
+    let potentials: Vec<f32> = bodies
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            bodies
+                .iter()
+                .enumerate()
+                .filter(|&(j, b)| i != j && !touching(a, b))
+                .map(|(_, b)| b.gm / a.position.distance(b.position))
+                .sum()
+        })
+        .collect();
+
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let mut correction = Vec3::ZERO;
+
+            for (j, b) in bodies.iter().enumerate() {
+                if i == j || touching(a, b) {
+                    continue;
+                }
+
+                let offset = a.position - b.position;
+                let r = offset.length();
+                let newtonian = -offset * (b.gm / r.powi(3));
+
+                let factor = -4. * potentials[i] - potentials[j]
+                    + a.velocity.length_squared()
+                    + 2. * b.velocity.length_squared()
+                    - 4. * a.velocity.dot(b.velocity)
+                    - 1.5 * (offset.dot(b.velocity) / r).powi(2)
+                    - 0.5 * offset.dot(b.acceleration);
+                correction += newtonian * factor;
+                correction += (a.velocity - b.velocity)
+                    * (b.gm / r.powi(3) * offset.dot(4. * a.velocity - 3. * b.velocity));
+                correction += b.acceleration * (3.5 * b.gm / r);
+            }
+
+            correction / c2
+        })
+        .collect()
+}
+
+/// Periapsis rotation of the selected body, or the lightest one, around the heaviest body
+#[derive(Resource, Default)]
+pub struct Precession {
+    body: Option<Entity>,
+    last_periapsis: Vec3,
+    pub name: String,
+    /// Rotation since tracking started, in radians
+    pub angle: f32,
+    /// Orbits completed since tracking started
+    pub orbits: f32,
+    /// General relativity prediction for the current orbit, in radians per orbit
+    pub predicted: f32,
+}
+
+impl Precession {
+    /// Measured rotation per orbit, in radians, once a full orbit is tracked
+    pub fn measured(&self) -> Option<f32> {
+        (self.orbits >= 1.).then(|| self.angle / self.orbits)
+    }
+}
+
+fn reset_precession(mut precession: ResMut<Precession>) {
+    *precession = Precession::default();
+}
+
+/// Follows the eccentricity (Laplace-Runge-Lenz) vector of the tracked orbit, it points to the
+/// periapsis
+fn track_precession(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    physics: Res<PhysicsSettings>,
+    selected: Res<SelectedBody>,
+    mut precession: ResMut<Precession>,
+    planets_q: Query<(Entity, &Planet, Option<&Name>)>,
+) {
+    let heaviest = |(_, a, _): &(Entity, &Planet, _), (_, b, _): &(Entity, &Planet, _)| {
+        a.mass().total_cmp(&b.mass())
+    };
+    let Some(central) = planets_q.iter().max_by(heaviest) else {
+        return;
+    };
+    let Some((entity, body, name)) = selected
+        .0
+        .and_then(|entity| planets_q.get(entity).ok())
+        .filter(|(entity, ..)| *entity != central.0)
+        .or_else(|| {
+            planets_q
+                .iter()
+                .filter(|(entity, ..)| *entity != central.0)
+                .min_by(heaviest)
+        })
+    else {
+        return;
+    };
+
+    let mu = physics.gravity.constant * (central.1.mass() + body.mass());
+    let r = body.position - central.1.position;
+    let v = body.velocity - central.1.velocity;
+    let normal = r.cross(v);
+    let periapsis = ((v.length_squared() - mu / r.length()) * r - r.dot(v) * v) / mu;
+    let eccentricity = periapsis.length();
+    // Semi-major axis from the vis-viva equation, unbound orbits don't precess
+    let semi_major_axis = 1. / (2. / r.length() - v.length_squared() / mu);
+    if semi_major_axis <= 0. || eccentricity >= 1. {
+        return;
+    }
+
+    if precession.body != Some(entity) {
+        *precession = Precession {
+            body: Some(entity),
+            last_periapsis: periapsis,
+            name: name.map_or("Body".to_string(), |name| name.to_string()),
+            ..default()
+        };
+        return;
+    }
+
+    let last = precession.last_periapsis;
+    precession.angle += last
+        .cross(periapsis)
+        .dot(normal.normalize())
+        .atan2(last.dot(periapsis));
+    precession.last_periapsis = periapsis;
+
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+    let period = TAU * (semi_major_axis.powi(3) / mu).sqrt();
+    precession.orbits += dt / period;
+    precession.predicted = 6. * PI * mu
+        / (physics.speed_of_light.powi(2) * semi_major_axis * (1. - eccentricity.powi(2)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Acceleration of a massless body around a fixed one with G M = 1 at the origin
+    fn acceleration(position: Vec3, velocity: Vec3, speed_of_light: f32) -> Vec3 {
+        let newtonian = -position / position.length().powi(3);
+        let bodies = [
+            PnBody {
+                position: Vec3::ZERO,
+                velocity: Vec3::ZERO,
+                gm: 1.,
+                radius: 0.01,
+                acceleration: Vec3::ZERO,
+            },
+            PnBody {
+                position,
+                velocity,
+                gm: 0.,
+                radius: 0.,
+                acceleration: newtonian,
+            },
+        ];
+
+        newtonian + post_newtonian_corrections(&bodies, speed_of_light)[1]
+    }
+
+    /// Mean periapsis rotation per orbit, in radians, of an orbit with a = 1 and e = 0.5
+    fn periapsis_shift(speed_of_light: f32, orbits: usize) -> f32 {
+        const STEPS_PER_ORBIT: usize = 20_000;
+        let dt = TAU / STEPS_PER_ORBIT as f32;
+        let mut position = Vec3::new(0.5, 0., 0.);
+        let mut velocity = Vec3::new(0., 3_f32.sqrt(), 0.);
+
+        let mut periapses = vec![position];
+        let mut distances = [position.length(); 3];
+        // Runge-Kutta 4 until the last periapsis
+        for _ in 0..2 * orbits * STEPS_PER_ORBIT {
+            if periapses.len() > orbits {
+                break;
+            }
+
+            let a = |p, v| acceleration(p, v, speed_of_light);
+            let (k1p, k1v) = (velocity, a(position, velocity));
+            let (k2p, k2v) = {
+                let v = velocity + k1v * (dt / 2.);
+                (v, a(position + k1p * (dt / 2.), v))
+            };
+            let (k3p, k3v) = {
+                let v = velocity + k2v * (dt / 2.);
+                (v, a(position + k2p * (dt / 2.), v))
+            };
+            let (k4p, k4v) = {
+                let v = velocity + k3v * dt;
+                (v, a(position + k3p * dt, v))
+            };
+            let next = position + (k1p + 2. * k2p + 2. * k3p + k4p) * (dt / 6.);
+            velocity += (k1v + 2. * k2v + 2. * k3v + k4v) * (dt / 6.);
+
+            distances = [distances[1], distances[2], next.length()];
+            // Only near the periapsis, rounding makes the flat apoapsis look like a minimum
+            if distances[1] < 1. && distances[1] < distances[0] && distances[1] <= distances[2] {
+                periapses.push(position);
+            }
+            position = next;
+        }
+
+        assert_eq!(periapses.len(), orbits + 1);
+        periapses
+            .windows(2)
+            .map(|pair| pair[0].cross(pair[1]).z.atan2(pair[0].dot(pair[1])))
+            .sum::<f32>()
+            / orbits as f32
+    }
+
+    #[test]
+    fn newtonian_orbits_stay_closed() {
+        assert!(periapsis_shift(1e6, 2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn periapsis_shift_matches_general_relativity() {
+        let speed_of_light: f32 = 22.;
+        // 6 pi G M / (c² a (1 - e²))
+        let predicted = 6. * PI / (speed_of_light.powi(2) * 0.75);
+        let measured = periapsis_shift(speed_of_light, 3);
+
+        assert!(
+            (measured - predicted).abs() < 0.05 * predicted,
+            "measured {measured}, predicted {predicted}"
+        );
+    }
+
+    #[test]
+    fn touching_bodies_have_no_correction() {
+        let body = |x: f32| PnBody {
+            position: Vec3::new(x, 0., 0.),
+            velocity: Vec3::new(0., x, 0.),
+            gm: 1.,
+            radius: 1.,
+            acceleration: Vec3::ZERO,
+        };
+
+        let corrections = post_newtonian_corrections(&[body(0.), body(1.5)], 1.);
+        assert_eq!(corrections, vec![Vec3::ZERO; 2]);
+    }
+}
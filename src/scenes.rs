@@ -0,0 +1,624 @@
+use std::{
+    f32::consts::TAU,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::data_path,
+    drag::Atmosphere,
+    environment::Environment,
+    events::SceneLoaded,
+    motion::BodyMotion,
+    particles::{ParticleCloud, TestParticles},
+    player::SelectedBody,
+    stars::Star,
+    surfaces::{PlanetKind, Surface},
+    view::{BodyMaterials, SphereMeshes},
+    AppState, PhysicsSettings, Planet, PlanetBundle,
+};
+
+const SCENES_DIR: &str = "scenes";
+const EARTH_ATMOSPHERE: Color = Color::rgba(0.4, 0.6, 1., 0.7);
+
+pub struct ScenesPlugin;
+
+impl Plugin for ScenesPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<CurrentScene>()
+            .add_systems(
+                OnEnter(AppState::Loading),
+                (clear_scene, load_scene).chain(),
+            );
+    }
+}
+
+/// Where a scene comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneSource {
+    /// Index into [`builtin_scenes`]
+    BuiltIn(usize),
+    File(PathBuf),
+}
+
+/// Scene loaded by the next `AppState::Loading`, and reloaded on restart
+#[derive(Resource, Debug, Clone)]
+pub struct CurrentScene(pub SceneSource);
+
+impl Default for CurrentScene {
+    fn default() -> Self {
+        CurrentScene(SceneSource::BuiltIn(0))
+    }
+}
+
+/// A scene as stored on disk, physical units like [`Planet`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub name: String,
+    /// Older scene files were all in the lab
+    #[serde(default)]
+    pub environment: Environment,
+    #[serde(default)]
+    pub physics: PhysicsSettings,
+    pub bodies: Vec<BodyDescription>,
+    /// Massless particles around the bodies
+    #[serde(default)]
+    pub particles: Vec<ParticleCloud>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyDescription {
+    pub name: String,
+    /// km
+    pub radius: f32,
+    /// g/cm³
+    pub density: f32,
+    /// km
+    pub position: Vec3,
+    /// km/s
+    #[serde(default)]
+    pub velocity: Vec3,
+    pub color: Color,
+    /// Makes the body a star, in solar luminosities
+    #[serde(default)]
+    pub luminosity: Option<f32>,
+    /// Procedural textures instead of the plain glassy material
+    #[serde(default)]
+    pub surface: Option<Surface>,
+    /// Rotation of the body, its spin pole is the local Y axis
+    #[serde(default)]
+    pub orientation: Quat,
+    /// rad/s, axis in world space
+    #[serde(default)]
+    pub angular_velocity: Vec3,
+    /// Pins the body or moves it along a path, gravity moves it when `None`
+    #[serde(default)]
+    pub motion: Option<BodyMotion>,
+    /// Coulombs, like charges repel
+    #[serde(default)]
+    pub charge: f32,
+    /// Gas that slows smaller bodies down, its haze is drawn by `surface`
+    #[serde(default)]
+    pub atmosphere: Option<Atmosphere>,
+}
+
+impl BodyDescription {
+    fn new(name: &str, radius: f32, density: f32, position: Vec3, color: Color) -> Self {
+        BodyDescription {
+            name: name.to_string(),
+            radius,
+            density,
+            position,
+            velocity: Vec3::ZERO,
+            color,
+            luminosity: None,
+            surface: None,
+            orientation: Quat::IDENTITY,
+            angular_velocity: Vec3::ZERO,
+            motion: None,
+            charge: 0.,
+            atmosphere: None,
+        }
+    }
+
+    fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    /// Tilts the pole towards +Z and spins the body around it, prograde for positive periods
+    fn with_spin(mut self, axial_tilt_degrees: f32, rotation_period_hours: f32) -> Self {
+        self.orientation = Quat::from_rotation_x(axial_tilt_degrees.to_radians());
+        self.angular_velocity =
+            self.orientation * Vec3::Y * (TAU / (rotation_period_hours * 3600.));
+        self
+    }
+
+    fn with_motion(mut self, motion: BodyMotion) -> Self {
+        self.motion = Some(motion);
+        self
+    }
+
+    /// Atmosphere `height` radii thick, `density` kg/m³ at the surface
+    fn with_atmosphere(mut self, height: f32, density: f32) -> Self {
+        self.atmosphere = Some(Atmosphere { height, density });
+        self
+    }
+
+    fn with_luminosity(mut self, luminosity: f32) -> Self {
+        self.luminosity = Some(luminosity);
+        self
+    }
+
+    fn with_surface(mut self, kind: PlanetKind, seed: u32, atmosphere: Option<Color>) -> Self {
+        self.surface = Some(Surface {
+            kind,
+            seed,
+            atmosphere,
+        });
+        self
+    }
+}
+
+pub fn builtin_scenes() -> Vec<SceneFile> {
+    vec![
+        SceneFile {
+            name: "Three bodies".to_string(),
+            environment: Environment::Lab,
+            physics: PhysicsSettings::default(),
+            bodies: vec![
+                BodyDescription::new(
+                    "Red",
+                    3000.,
+                    8.,
+                    Vec3::new(-39_000., 1_800., 1_400.),
+                    Color::RED,
+                )
+                .with_surface(PlanetKind::Lava, 1, None)
+                .with_spin(15., 10.),
+                BodyDescription::new(
+                    "Green",
+                    3000.,
+                    8.,
+                    Vec3::new(18_000., -1_800., 14_000.),
+                    Color::GREEN,
+                )
+                .with_surface(
+                    PlanetKind::GasGiant,
+                    2,
+                    Some(Color::rgba(0.6, 1., 0.6, 0.5)),
+                )
+                .with_spin(40., 6.),
+                BodyDescription::new(
+                    "Blue",
+                    3000.,
+                    8.,
+                    Vec3::new(-25_000., -18_500., -14_000.),
+                    Color::BLUE,
+                )
+                .with_surface(PlanetKind::Ice, 3, None)
+                .with_spin(5., 14.),
+            ],
+            particles: Vec::new(),
+        },
+        SceneFile {
+            name: "Earth and Moon".to_string(),
+            environment: Environment::Space { milky_way: true },
+            physics: PhysicsSettings::default(),
+            bodies: vec![
+                BodyDescription::new("Earth", 6378., 5.51, Vec3::ZERO, Color::BLUE)
+                    .with_surface(PlanetKind::Rocky, 3, Some(EARTH_ATMOSPHERE))
+                    .with_spin(23.44, 23.93),
+                BodyDescription::new(
+                    "Moon",
+                    1737.4,
+                    3.34,
+                    Vec3::new(0., 384_400., 0.),
+                    Color::WHITE,
+                )
+                .with_velocity(Vec3::new(1.022, 0., 0.))
+                .with_surface(PlanetKind::Rocky, 5, None)
+                .with_spin(6.68, 655.7),
+            ],
+            particles: Vec::new(),
+        },
+        inner_solar_system(),
+        ring_formation(),
+        fixed_attractors(),
+        ringed_giant(),
+        perihelion_precession(),
+        aerobraking(),
+    ]
+}
+
+/// The Sun and the inner planets on circular orbits in the XZ plane.
+/// The Sun sits away from the origin so the camera doesn't start inside it.
+fn inner_solar_system() -> SceneFile {
+    let sun = Vec3::new(0., 0., -1_000_000.);
+    // Name, radius, density, orbit radius, orbital speed, color, atmosphere
+    let planets = [
+        ("Mercury", 2_439.7, 5.43, 57.9e6, 47.36, Color::GRAY, None),
+        (
+            "Venus",
+            6_051.8,
+            5.24,
+            108.2e6,
+            35.02,
+            Color::BEIGE,
+            Some(Color::rgba(1., 0.9, 0.6, 0.8)),
+        ),
+        (
+            "Earth",
+            6_378.,
+            5.51,
+            149.6e6,
+            29.78,
+            Color::BLUE,
+            Some(EARTH_ATMOSPHERE),
+        ),
+        (
+            "Mars",
+            3_389.5,
+            3.93,
+            227.9e6,
+            24.07,
+            Color::ORANGE_RED,
+            Some(Color::rgba(1., 0.6, 0.4, 0.3)),
+        ),
+    ];
+
+    // Axial tilt in degrees, rotation period in hours, in the same order
+    let spins = [
+        (0.03, 1_407.6),
+        (177.4, 5_832.5),
+        (23.44, 23.93),
+        (25.19, 24.62),
+    ];
+
+    let mut bodies =
+        vec![
+            BodyDescription::new("Sun", 696_000., 1.41, sun, Color::rgb(1., 0.85, 0.6))
+                .with_luminosity(1.)
+                .with_spin(7.25, 609.1),
+        ];
+    bodies.extend(planets.iter().zip(spins).zip(1..).map(
+        |((&(name, radius, density, orbit, speed, color, atmosphere), (tilt, period)), seed)| {
+            BodyDescription::new(name, radius, density, sun + Vec3::X * orbit, color)
+                .with_velocity(Vec3::NEG_Z * speed)
+                .with_surface(PlanetKind::Rocky, seed, atmosphere)
+                .with_spin(tilt, period)
+        },
+    ));
+    bodies.push(
+        BodyDescription::new(
+            "Moon",
+            1737.4,
+            3.34,
+            sun + Vec3::X * (149.6e6 + 384_400.),
+            Color::WHITE,
+        )
+        .with_velocity(Vec3::NEG_Z * (29.78 + 1.022))
+        .with_surface(PlanetKind::Rocky, 5, None)
+        .with_spin(6.68, 655.7),
+    );
+
+    SceneFile {
+        name: "Inner solar system".to_string(),
+        environment: Environment::Space { milky_way: true },
+        physics: PhysicsSettings::default(),
+        bodies,
+        particles: Vec::new(),
+    }
+}
+
+/// An icy moon on an eccentric orbit that dips inside the Roche limit of a gas giant,
+/// about 158 000 km, and breaks into a ring
+fn ring_formation() -> SceneFile {
+    SceneFile {
+        name: "Ring formation".to_string(),
+        environment: Environment::Space { milky_way: true },
+        physics: PhysicsSettings {
+            roche_breakup: true,
+            tidal_dissipation: 1e-5,
+            ..default()
+        },
+        bodies: vec![
+            BodyDescription::new("Giant", 58_232., 0.687, Vec3::ZERO, Color::BEIGE)
+                .with_surface(PlanetKind::GasGiant, 4, None)
+                .with_spin(26.7, 10.7),
+            // Apoapsis at 400 000 km, periapsis at 120 000 km
+            BodyDescription::new(
+                "Moon",
+                1_000.,
+                0.5,
+                Vec3::new(400_000., 0., 0.),
+                Color::ALICE_BLUE,
+            )
+            .with_velocity(Vec3::new(0., 0., -6.61))
+            .with_surface(PlanetKind::Ice, 6, None),
+        ],
+        particles: Vec::new(),
+    }
+}
+
+/// A pinned planet and a moon on a scripted circle, faster than gravity alone would allow,
+/// stirring up small bodies on orbits around them
+fn fixed_attractors() -> SceneFile {
+    // Name, orbit radius, circular speed around the anchor
+    let orbiters = [
+        ("Inner", 60_000., 2.58),
+        ("Middle", 110_000., 1.9),
+        ("Outer", 300_000., 1.15),
+    ];
+
+    let mut bodies = vec![
+        BodyDescription::new("Anchor", 6_378., 5.51, Vec3::ZERO, Color::SEA_GREEN)
+            .with_surface(PlanetKind::Rocky, 7, Some(EARTH_ATMOSPHERE))
+            .with_motion(BodyMotion::Static),
+        BodyDescription::new("Shepherd", 1_737.4, 3.34, Vec3::ZERO, Color::SILVER)
+            .with_surface(PlanetKind::Rocky, 8, None)
+            .with_motion(BodyMotion::Circle {
+                center: Vec3::ZERO,
+                axis: Vec3::Y,
+                radius: 200_000.,
+                period: 500_000.,
+                phase: 0.,
+            }),
+    ];
+    bodies.extend(orbiters.iter().map(|&(name, orbit, speed)| {
+        BodyDescription::new(name, 500., 3., Vec3::new(orbit, 0., 0.), Color::GOLD)
+            .with_velocity(Vec3::new(0., 0., -speed))
+    }));
+
+    SceneFile {
+        name: "Fixed attractors".to_string(),
+        environment: Environment::Lab,
+        physics: PhysicsSettings::default(),
+        bodies,
+        particles: Vec::new(),
+    }
+}
+
+/// A Saturn-like planet with 100 000 ring particles and a moon just outside the rings
+fn ringed_giant() -> SceneFile {
+    SceneFile {
+        name: "Ringed giant".to_string(),
+        environment: Environment::Space { milky_way: true },
+        physics: PhysicsSettings::default(),
+        bodies: vec![
+            BodyDescription::new("Giant", 58_232., 0.687, Vec3::ZERO, Color::BEIGE)
+                .with_surface(PlanetKind::GasGiant, 9, None)
+                .with_spin(26.7, 10.7),
+            // Circular orbit at 185 000 km
+            BodyDescription::new(
+                "Moon",
+                198.,
+                1.15,
+                Vec3::new(185_000., 0., 0.),
+                Color::ALICE_BLUE,
+            )
+            .with_velocity(Vec3::new(0., 0., -14.3))
+            .with_surface(PlanetKind::Ice, 10, None),
+        ],
+        particles: vec![ParticleCloud::Ring {
+            around: "Giant".to_string(),
+            inner_radius: 74_500.,
+            outer_radius: 136_800.,
+            count: 100_000,
+            thickness: 100.,
+            color: Color::rgb(0.9, 0.85, 0.75),
+            seed: 1,
+        }],
+    }
+}
+
+/// Mercury on an orbit ten times tighter, with the speed of light lowered to 3000 km/s.
+/// The periapsis advances about 2.9° per orbit, an orbit takes about 2.8 days.
+fn perihelion_precession() -> SceneFile {
+    let sun = Vec3::new(0., 0., -1e6);
+
+    SceneFile {
+        name: "Perihelion precession".to_string(),
+        environment: Environment::Space { milky_way: true },
+        physics: PhysicsSettings {
+            post_newtonian: true,
+            speed_of_light: 3_000.,
+            ..default()
+        },
+        bodies: vec![
+            BodyDescription::new("Sun", 696_000., 1.41, sun, Color::rgb(1., 0.85, 0.6))
+                .with_luminosity(1.),
+            // Semi-major axis 5.79 million km, eccentricity 0.2056, starting at periapsis
+            BodyDescription::new("Mercury", 2_439.7, 5.43, sun + Vec3::X * 4.6e6, Color::GRAY)
+                .with_velocity(Vec3::NEG_Z * 186.6)
+                .with_surface(PlanetKind::Rocky, 11, None),
+        ],
+        particles: Vec::new(),
+    }
+}
+
+/// Two probes in the thick atmosphere of an Earth: one dips into it at each periapsis and
+/// its orbit shrinks, the other starts on a low circular orbit and spirals in until the
+/// Earth absorbs it
+fn aerobraking() -> SceneFile {
+    SceneFile {
+        name: "Aerobraking".to_string(),
+        environment: Environment::Space { milky_way: true },
+        physics: PhysicsSettings {
+            merge_on_contact: true,
+            ..default()
+        },
+        bodies: vec![
+            BodyDescription::new("Earth", 6_378., 5.51, Vec3::ZERO, Color::BLUE)
+                .with_surface(PlanetKind::Rocky, 12, Some(EARTH_ATMOSPHERE))
+                .with_spin(23.44, 23.93)
+                .with_atmosphere(0.05, 1.2),
+            // Periapsis 150 km above the surface, apoapsis at 60 000 km
+            BodyDescription::new("Probe", 10., 1., Vec3::new(6_528., 0., 0.), Color::ORANGE)
+                .with_velocity(Vec3::new(0., 0., -10.49)),
+            // Circular orbit 280 km above the surface
+            BodyDescription::new(
+                "Satellite",
+                5.,
+                1.,
+                Vec3::new(-6_658., 0., 0.),
+                Color::SILVER,
+            )
+            .with_velocity(Vec3::new(0., 0., 7.737)),
+        ],
+        particles: Vec::new(),
+    }
+}
+
+pub fn scenes_dir() -> Option<PathBuf> {
+    let dir = data_path(SCENES_DIR)?;
+
+    if let Err(err) = fs::create_dir_all(&dir) {
+        warn!("Could not create scenes directory {}: {err}", dir.display());
+        return None;
+    }
+
+    Some(dir)
+}
+
+/// Scene files saved by the user, sorted by name
+pub fn saved_scenes() -> Vec<PathBuf> {
+    let Some(dir) = scenes_dir() else {
+        return Vec::new();
+    };
+
+    let mut scenes: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+        .collect();
+    scenes.sort();
+
+    scenes
+}
+
+fn read_scene(path: &Path) -> Result<SceneFile, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    ron::from_str(&contents).map_err(|err| err.to_string())
+}
+
+/// Writes the scene to a new file in the scenes directory
+pub fn save_scene(scene: &SceneFile) -> Result<PathBuf, String> {
+    let dir = scenes_dir().ok_or("no scenes directory")?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = dir.join(format!("scene-{timestamp}.ron"));
+
+    let contents = ron::ser::to_string_pretty(scene, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    fs::write(&path, contents).map_err(|err| err.to_string())?;
+
+    Ok(path)
+}
+
+/// Removes the bodies of the previous scene
+fn clear_scene(
+    mut commands: Commands,
+    mut selected: ResMut<SelectedBody>,
+    planets_q: Query<Entity, With<Planet>>,
+) {
+    for entity in &planets_q {
+        commands.entity(entity).despawn_recursive();
+    }
+    selected.0 = None;
+}
+
+fn load_scene(
+    mut commands: Commands,
+    sphere_meshes: Res<SphereMeshes>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut body_materials: ResMut<BodyMaterials>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut loaded: EventWriter<SceneLoaded>,
+    current_scene: Res<CurrentScene>,
+) {
+    let mut scene = match &current_scene.0 {
+        SceneSource::BuiltIn(index) => builtin_scenes().swap_remove(*index),
+        SceneSource::File(path) => match read_scene(path) {
+            Ok(scene) => scene,
+            Err(err) => {
+                warn!("Could not load scene {}: {err}", path.display());
+                builtin_scenes().swap_remove(0)
+            }
+        },
+    };
+
+    info!("Loading scene {}", scene.name);
+    loaded.send(SceneLoaded {
+        name: scene.name.clone(),
+    });
+    scene.physics.gravity.law = scene.physics.gravity.law.validated();
+    commands.insert_resource(scene.environment);
+    commands.insert_resource(TestParticles(
+        scene
+            .particles
+            .iter()
+            .flat_map(|cloud| cloud.generate(&scene.bodies, &scene.physics.gravity))
+            .collect(),
+    ));
+    commands.insert_resource(scene.physics);
+
+    for body in scene.bodies {
+        // Kinematic bodies start on their path
+        let (position, velocity) = body
+            .motion
+            .as_ref()
+            .and_then(|motion| motion.sample(0.))
+            .unwrap_or((body.position, body.velocity));
+
+        let mut entity = commands.spawn((
+            PlanetBundle::new(
+                &sphere_meshes,
+                body.radius,
+                body.density,
+                body_materials.get_or_add(body.color, &mut materials, |base_color| {
+                    StandardMaterial {
+                        base_color,
+                        specular_transmission: 0.9,
+                        diffuse_transmission: 1.0,
+                        thickness: 1.8,
+                        ior: 1.5,
+                        perceptual_roughness: 0.12,
+                        ..default()
+                    }
+                }),
+                position,
+                Some(velocity),
+            )
+            .with_spin(body.orientation, body.angular_velocity)
+            .with_charge(body.charge),
+            Name::new(body.name),
+        ));
+
+        if let Some(luminosity) = body.luminosity {
+            entity.insert(Star { luminosity });
+        }
+
+        if let Some(surface) = body.surface {
+            entity.insert(surface);
+        }
+
+        if let Some(motion) = body.motion {
+            entity.insert(motion);
+        }
+
+        if let Some(atmosphere) = body.atmosphere {
+            entity.insert(atmosphere);
+        }
+    }
+
+    next_state.set(AppState::Simulating);
+}
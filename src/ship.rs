@@ -0,0 +1,316 @@
+use std::f32::consts::PI;
+
+use bevy::{prelude::*, transform::TransformSystem};
+
+use crate::{
+    calculate_acceleration_velocity, end_physics_tick,
+    input::{Action, ActionState},
+    player::{CameraMode, CameraSettings, MainCamera, Player},
+    utils::KG_PER_KM3_PER_G_CM3,
+    view::{SphereMeshes, ViewScale},
+    AppState, GameSpeed, PhysicsSettings, Planet, PlanetBundle, TIME_SPEED,
+};
+
+/// km, drawn at the minimum on-screen size anyway
+const SHIP_RADIUS: f32 = 0.02;
+/// kg
+const DRY_MASS: f32 = 10_000.;
+/// kg
+const FUEL_MASS: f32 = 30_000.;
+/// g/cm³, the body weighs as much as the fueled ship, mostly empty space
+const SHIP_DENSITY: f32 = (DRY_MASS + FUEL_MASS)
+    / (4. / 3. * PI * SHIP_RADIUS * SHIP_RADIUS * SHIP_RADIUS * KG_PER_KM3_PER_G_CM3);
+/// km/s
+const EXHAUST_VELOCITY: f32 = 3.;
+/// kN, forward only
+const MAIN_THRUST: f32 = 20.;
+/// kN, in every direction
+const RCS_THRUST: f32 = 5.;
+/// World units between the camera and the ship
+const CAMERA_DISTANCE: f32 = 0.3;
+
+pub struct ShipPlugin;
+
+impl Plugin for ShipPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .add_systems(OnEnter(AppState::Loading), leave_ship)
+            .add_systems(
+                Update,
+                (
+                    toggle_ship,
+                    roll_ship.run_if(resource_equals(CameraMode::Ship)),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Simulating)),
+            )
+            .add_systems(
+                FixedUpdate,
+                fire_engines
+                    .after(calculate_acceleration_velocity)
+                    .before(end_physics_tick)
+                    .run_if(in_state(AppState::Simulating))
+                    .run_if(resource_equals(CameraMode::Ship)),
+            )
+            .add_systems(
+                PostUpdate,
+                ship_camera
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(resource_equals(CameraMode::Ship)),
+            );
+    }
+}
+
+/// Body flown by the player. Gravity pulls it like any other body, its engines push it
+/// where the camera looks.
+#[derive(Component)]
+pub struct Ship {
+    /// kg left in the tanks
+    pub fuel: f32,
+}
+
+impl Ship {
+    /// kg, the body mass stays the same, this is what the engines push
+    fn mass(&self) -> f32 {
+        DRY_MASS + self.fuel
+    }
+
+    /// km/s the remaining fuel can still add, from the rocket equation
+    pub fn delta_v(&self) -> f32 {
+        EXHAUST_VELOCITY * (self.mass() / DRY_MASS).ln()
+    }
+
+    /// Burns the fuel for `delta_v` km/s at once, or all that is left.
+    /// Returns the km/s actually gained.
+    pub fn burn(&mut self, delta_v: f32) -> f32 {
+        let burned = (self.mass() * (1. - (-delta_v / EXHAUST_VELOCITY).exp())).min(self.fuel);
+        let gained = EXHAUST_VELOCITY * (self.mass() / (self.mass() - burned)).ln();
+        self.fuel -= burned;
+        gained
+    }
+
+    /// Fraction of the fuel left, in [0, 1]
+    pub fn fuel_fraction(&self) -> f32 {
+        self.fuel / FUEL_MASS
+    }
+}
+
+/// Spawns a ship in front of the camera on a circular orbit around the nearest body, heading
+/// where the camera looks. Pressing again drops back to free flight.
+#[allow(clippy::too_many_arguments)]
+fn toggle_ship(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    physics: Res<PhysicsSettings>,
+    view_scale: Res<ViewScale>,
+    sphere_meshes: Res<SphereMeshes>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut camera_mode: ResMut<CameraMode>,
+    camera_q: Query<&Transform, With<MainCamera>>,
+    planets_q: Query<(Entity, &Planet, Has<Ship>)>,
+) {
+    if !actions.just_pressed(Action::ToggleShip) {
+        return;
+    }
+
+    if *camera_mode == CameraMode::Ship {
+        for (entity, _, ship) in &planets_q {
+            if ship {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        *camera_mode = CameraMode::FreeFly;
+        return;
+    }
+
+    let camera = camera_q.single();
+    let position = view_scale.to_physical(camera.translation + camera.forward() * CAMERA_DISTANCE);
+
+    let nearest = planets_q
+        .iter()
+        .map(|(_, planet, _)| planet)
+        .min_by(|a, b| {
+            let surface = |planet: &Planet| planet.position.distance(position) - planet.radius;
+            surface(a).total_cmp(&surface(b))
+        });
+    let velocity = nearest.map_or(Vec3::ZERO, |body| {
+        let offset = position - body.position;
+        let radial = offset.normalize_or_zero();
+        let heading = (*camera.forward() - radial * camera.forward().dot(radial))
+            .try_normalize()
+            .unwrap_or_else(|| radial.any_orthonormal_vector());
+        let speed = physics.gravity.circular_speed(body.mass(), offset.length());
+        body.velocity + heading * speed
+    });
+
+    commands.spawn((
+        PlanetBundle::new(
+            &sphere_meshes,
+            SHIP_RADIUS,
+            SHIP_DENSITY,
+            materials.add(StandardMaterial {
+                base_color: Color::ORANGE,
+                unlit: true,
+                ..default()
+            }),
+            position,
+            Some(velocity),
+        ),
+        Name::new("Ship"),
+        Ship { fuel: FUEL_MASS },
+    ));
+    *camera_mode = CameraMode::Ship;
+}
+
+fn leave_ship(mut camera_mode: ResMut<CameraMode>) {
+    if *camera_mode == CameraMode::Ship {
+        *camera_mode = CameraMode::FreeFly;
+    }
+}
+
+/// The ship turns with the camera, mouse look is shared with free flight
+fn roll_ship(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    settings: Res<CameraSettings>,
+    mut camera_q: Query<&mut Transform, With<MainCamera>>,
+) {
+    let roll = actions.axis(Action::RollRight, Action::RollLeft);
+    camera_q
+        .single_mut()
+        .rotate_local_z(roll * settings.roll_speed * time.delta_seconds());
+}
+
+/// Main engine on forward, RCS thrusters on the other movement keys.
+/// Applied as a velocity change after the gravity step, like the other non-conservative forces.
+fn fire_engines(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    actions: Res<ActionState>,
+    camera_q: Query<&Transform, With<MainCamera>>,
+    mut ship_q: Query<(&mut Ship, &mut Planet)>,
+) {
+    let Ok((mut ship, mut planet)) = ship_q.get_single_mut() else {
+        return;
+    };
+    if ship.fuel <= 0. {
+        return;
+    }
+
+    // Camera space, forward is -Z
+    let rcs = Vec3::new(
+        actions.axis(Action::MoveLeft, Action::MoveRight),
+        actions.axis(Action::MoveDown, Action::MoveUp),
+        actions.value(Action::MoveBack),
+    )
+    .clamp_length_max(1.)
+        * RCS_THRUST;
+    let thrust = rcs + Vec3::NEG_Z * actions.value(Action::MoveForward) * MAIN_THRUST;
+    if thrust == Vec3::ZERO {
+        return;
+    }
+
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+    // kN over km/s is kg/s, the last burn stops when the tanks run dry
+    let burned = (thrust.length() / EXHAUST_VELOCITY * dt).min(ship.fuel);
+    let delta_v = EXHAUST_VELOCITY * (ship.mass() / (ship.mass() - burned)).ln();
+
+    planet.velocity += camera_q.single().rotation * thrust.normalize() * delta_v;
+    ship.fuel -= burned;
+}
+
+/// Chase camera just behind the ship. The free-fly anchor follows, so leaving the ship keeps
+/// the view where it is. Drops back to free flight if the ship is gone, like after a merge.
+fn ship_camera(
+    view_scale: Res<ViewScale>,
+    mut camera_mode: ResMut<CameraMode>,
+    ship_q: Query<&Planet, With<Ship>>,
+    mut camera_q: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
+    mut player_q: Query<(&mut Transform, &mut Player), Without<MainCamera>>,
+) {
+    let Ok(ship) = ship_q.get_single() else {
+        *camera_mode = CameraMode::FreeFly;
+        return;
+    };
+
+    let mut camera_transform = camera_q.single_mut();
+    camera_transform.translation =
+        view_scale.to_world(ship.position) - camera_transform.forward() * CAMERA_DISTANCE;
+
+    let (mut player_transform, mut player) = player_q.single_mut();
+    player_transform.translation = camera_transform.translation;
+    player.stop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() <= 1e-4 * expected.abs().max(1.),
+            "{value} != {expected}"
+        );
+    }
+
+    #[test]
+    fn full_tanks_follow_the_rocket_equation() {
+        let ship = Ship { fuel: FUEL_MASS };
+
+        // Δv = ve ln(m0 / m1)
+        assert_close(ship.delta_v(), 3. * 4_f32.ln());
+        assert_close(Ship { fuel: 0. }.delta_v(), 0.);
+    }
+
+    #[test]
+    fn burn_uses_the_rocket_equation() {
+        let mut ship = Ship { fuel: FUEL_MASS };
+        let full = ship.delta_v();
+
+        assert_close(ship.burn(1.), 1.);
+        // m1 = m0 e^(-Δv / ve)
+        assert_close(
+            ship.mass(),
+            (DRY_MASS + FUEL_MASS) * (-1. / EXHAUST_VELOCITY).exp(),
+        );
+        assert_close(ship.delta_v(), full - 1.);
+    }
+
+    #[test]
+    fn burns_add_up() {
+        let mut once = Ship { fuel: FUEL_MASS };
+        let mut twice = Ship { fuel: FUEL_MASS };
+        once.burn(2.);
+        twice.burn(1.);
+        twice.burn(1.);
+
+        assert_close(once.fuel, twice.fuel);
+    }
+
+    #[test]
+    fn burn_stops_when_the_tanks_run_dry() {
+        let mut ship = Ship { fuel: FUEL_MASS };
+        let full = ship.delta_v();
+
+        assert_close(ship.burn(100.), full);
+        assert_eq!(ship.fuel, 0.);
+        assert_eq!(ship.burn(1.), 0.);
+        assert_eq!(ship.fuel_fraction(), 0.);
+    }
+
+    #[test]
+    fn body_weighs_as_much_as_the_ship() {
+        let body = Planet {
+            radius: SHIP_RADIUS,
+            density: SHIP_DENSITY,
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            acceleration: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+            angular_velocity: Vec3::ZERO,
+            charge: 0.,
+        };
+
+        assert_close(body.mass(), Ship { fuel: FUEL_MASS }.mass());
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+
+use crate::LabLight;
+
+/// Light output per solar luminosity, bright enough to light a planet 1 AU away at the
+/// default view scale
+const LUMENS_PER_SOLAR_LUMINOSITY: f32 = 1e16;
+const STAR_LIGHT_RANGE: f32 = 1e7; // world units
+/// Emissive strength of the star surface, high enough to bloom
+const STAR_EMISSIVE: f32 = 20.;
+const LAB_AMBIENT_BRIGHTNESS: f32 = 10.;
+/// Keeps the night side of planets barely visible
+const SPACE_AMBIENT_BRIGHTNESS: f32 = 0.5;
+
+pub struct StarsPlugin;
+
+impl Plugin for StarsPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .add_systems(Update, (light_up_stars, lab_lights));
+    }
+}
+
+/// Body that emits light, the planets are lit by it instead of the lab lights
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Star {
+    /// In solar luminosities
+    pub luminosity: f32,
+}
+
+fn light_up_stars(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    stars_q: Query<(Entity, &Star, &Handle<StandardMaterial>), Added<Star>>,
+) {
+    for (entity, star, material) in &stars_q {
+        let mut entity = commands.entity(entity);
+
+        // Copied, the body material is shared with other bodies of the same color
+        if let Some(material) = materials.get(material).cloned() {
+            entity.insert(materials.add(StandardMaterial {
+                emissive: material.base_color.as_rgba_linear() * STAR_EMISSIVE,
+                specular_transmission: 0.,
+                diffuse_transmission: 0.,
+                ..material
+            }));
+        }
+
+        entity
+            // The light sits inside the body, it would shadow everything otherwise
+            .insert(NotShadowCaster)
+            .with_children(|parent| {
+                parent.spawn(PointLightBundle {
+                    point_light: PointLight {
+                        intensity: star.luminosity * LUMENS_PER_SOLAR_LUMINOSITY,
+                        range: STAR_LIGHT_RANGE,
+                        shadows_enabled: true,
+                        ..default()
+                    },
+                    ..default()
+                });
+            });
+    }
+}
+
+/// The fixed lab lights only light scenes without stars
+fn lab_lights(
+    mut ambient_light: ResMut<AmbientLight>,
+    stars_q: Query<(), With<Star>>,
+    mut lights_q: Query<&mut Visibility, With<LabLight>>,
+) {
+    let has_stars = !stars_q.is_empty();
+
+    let brightness = if has_stars {
+        SPACE_AMBIENT_BRIGHTNESS
+    } else {
+        LAB_AMBIENT_BRIGHTNESS
+    };
+    if ambient_light.brightness != brightness {
+        ambient_light.brightness = brightness;
+    }
+
+    let visibility = if has_stars {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut light_visibility in &mut lights_q {
+        if *light_visibility != visibility {
+            *light_visibility = visibility;
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::{
+    asset::load_internal_asset,
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{AsBindGroup, Extent3d, ShaderRef, TextureDimension, TextureFormat},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{utils::fbm, view::SphereMeshes};
+
+const TEXTURE_WIDTH: usize = 512;
+const TEXTURE_HEIGHT: usize = 256;
+const ATMOSPHERE_SCALE: f32 = 1.06; // shell radius, in body radii
+const ATMOSPHERE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x3c5d_8f1e_9a27_4b6c_b0d4_7e21_a9f3_5c80);
+/// Emissive strength of lava cracks, high enough to bloom
+const LAVA_EMISSIVE: f32 = 8.;
+
+pub struct SurfacesPlugin;
+
+impl Plugin for SurfacesPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            ATMOSPHERE_SHADER_HANDLE,
+            "atmosphere.wgsl",
+            Shader::from_wgsl
+        );
+
+        app //
+            .add_plugins(MaterialPlugin::<AtmosphereMaterial>::default())
+            .add_systems(Update, generate_surfaces);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlanetKind {
+    Rocky,
+    GasGiant,
+    Ice,
+    Lava,
+}
+
+/// Procedural look of a body, its textures are generated once when it spawns.
+/// The textures are greyscale, the body's `base_color` tints them.
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Surface {
+    pub kind: PlanetKind,
+    pub seed: u32,
+    /// Color of the glow around the body, no atmosphere when `None`
+    #[serde(default)]
+    pub atmosphere: Option<Color>,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Clone)]
+struct AtmosphereMaterial {
+    #[uniform(0)]
+    color: Color,
+    /// Higher keeps the glow closer to the limb
+    #[uniform(1)]
+    power: f32,
+}
+
+impl Material for AtmosphereMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ATMOSPHERE_SHADER_HANDLE.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+fn generate_surfaces(
+    mut commands: Commands,
+    sphere_meshes: Res<SphereMeshes>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut atmosphere_materials: ResMut<Assets<AtmosphereMaterial>>,
+    planets_q: Query<(Entity, &Surface, &Handle<StandardMaterial>), Added<Surface>>,
+) {
+    for (entity, surface, material) in &planets_q {
+        // The body material is shared with other bodies of the same color
+        let base_color = materials
+            .get(material)
+            .map_or(Color::WHITE, |material| material.base_color);
+        let material = materials.add(StandardMaterial {
+            base_color,
+            ..surface_material(surface, &mut images)
+        });
+        commands.entity(entity).insert(material);
+
+        let Some(atmosphere) = surface.atmosphere else {
+            continue;
+        };
+
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                MaterialMeshBundle {
+                    mesh: sphere_meshes.level(3),
+                    material: atmosphere_materials.add(AtmosphereMaterial {
+                        color: atmosphere,
+                        power: 3.,
+                    }),
+                    // Relative to the unit sphere of the body
+                    transform: Transform::from_scale(Vec3::splat(ATMOSPHERE_SCALE)),
+                    ..default()
+                },
+                NotShadowCaster,
+            ));
+        });
+    }
+}
+
+/// Texel of a surface texture
+struct Texel {
+    color: Vec3,
+    height: f32,
+    /// Light given off, lava only
+    glow: Vec3,
+}
+
+fn surface_texel(kind: PlanetKind, point: Vec3, seed: u32) -> Texel {
+    let smoothstep = |edge0: f32, edge1: f32, x: f32| {
+        let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+        t * t * (3. - 2. * t)
+    };
+    // Thin lines where the noise crosses its middle value
+    let ridges = |point: Vec3, seed: u32| 1. - (2. * fbm(point, seed, 4) - 1.).abs();
+
+    match kind {
+        PlanetKind::Rocky => {
+            let height = fbm(point * 3., seed, 6);
+            let lowlands = Vec3::splat(0.4).lerp(Vec3::splat(0.8), smoothstep(0.3, 0.5, height));
+            let color = lowlands.lerp(Vec3::ONE, smoothstep(0.6, 0.75, height));
+
+            Texel {
+                color,
+                height,
+                glow: Vec3::ZERO,
+            }
+        }
+        PlanetKind::GasGiant => {
+            // Latitude bands, bent by turbulence
+            let turbulence = fbm(point * 4., seed, 4);
+            let band = ((point.y * 8. + turbulence * 2.) * PI).sin() * 0.5 + 0.5;
+            let color = Vec3::splat(0.6).lerp(Vec3::ONE, band);
+
+            Texel {
+                color,
+                height: band * 0.2,
+                glow: Vec3::ZERO,
+            }
+        }
+        PlanetKind::Ice => {
+            let frost = fbm(point * 5., seed, 5);
+            let crack = smoothstep(0.9, 1., ridges(point * 8., seed.wrapping_add(100)));
+            let color = Vec3::splat(1. - frost * 0.3) * (1. - crack * 0.5);
+
+            Texel {
+                color,
+                height: frost - crack * 0.3,
+                glow: Vec3::ZERO,
+            }
+        }
+        PlanetKind::Lava => {
+            let crust = fbm(point * 4., seed, 6);
+            let crack = smoothstep(0.85, 1., ridges(point * 6., seed.wrapping_add(100)));
+            let color = Vec3::splat(0.1).lerp(Vec3::splat(0.3), crust);
+
+            Texel {
+                color,
+                height: crust - crack * 0.5,
+                glow: Vec3::new(1., 0.35, 0.05) * crack,
+            }
+        }
+    }
+}
+
+fn texture(data: Vec<u8>, format: TextureFormat) -> Image {
+    Image::new(
+        Extent3d {
+            width: TEXTURE_WIDTH as u32,
+            height: TEXTURE_HEIGHT as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        format,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+/// Generates the color, normal and emissive maps of a surface, laid out like the UVs of
+/// `Sphere::mesh().ico()`
+fn surface_material(surface: &Surface, images: &mut Assets<Image>) -> StandardMaterial {
+    let texels: Vec<Texel> = (0..TEXTURE_WIDTH * TEXTURE_HEIGHT)
+        .map(|i| {
+            let u = ((i % TEXTURE_WIDTH) as f32 + 0.5) / TEXTURE_WIDTH as f32;
+            let v = ((i / TEXTURE_WIDTH) as f32 + 0.5) / TEXTURE_HEIGHT as f32;
+            let inclination = v * PI;
+            let azimuth = (0.5 - u) * TAU;
+            let point = Vec3::new(
+                inclination.sin() * azimuth.cos(),
+                inclination.cos(),
+                inclination.sin() * azimuth.sin(),
+            );
+
+            surface_texel(surface.kind, point, surface.seed)
+        })
+        .collect();
+
+    let to_bytes = |color: Vec3| {
+        let color = (color.clamp(Vec3::ZERO, Vec3::ONE) * 255.).round();
+        [color.x as u8, color.y as u8, color.z as u8, 255]
+    };
+
+    // Normals from the height slope, in tangent space
+    let (bumpiness, roughness) = match surface.kind {
+        PlanetKind::Rocky => (3., 0.9),
+        PlanetKind::GasGiant => (0.3, 0.6),
+        PlanetKind::Ice => (1., 0.25),
+        PlanetKind::Lava => (2., 0.85),
+    };
+    let height = |x: usize, y: usize| texels[y * TEXTURE_WIDTH + x].height;
+    let normals = (0..TEXTURE_WIDTH * TEXTURE_HEIGHT).flat_map(|i| {
+        let (x, y) = (i % TEXTURE_WIDTH, i / TEXTURE_WIDTH);
+        let slope_u =
+            height((x + 1) % TEXTURE_WIDTH, y) - height((x + TEXTURE_WIDTH - 1) % TEXTURE_WIDTH, y);
+        let slope_v = height(x, (y + 1).min(TEXTURE_HEIGHT - 1)) - height(x, y.saturating_sub(1));
+        let scale = bumpiness * TEXTURE_HEIGHT as f32 / 32.;
+        let normal = Vec3::new(-slope_u * scale, -slope_v * scale, 1.).normalize();
+
+        to_bytes(normal * 0.5 + 0.5)
+    });
+
+    let color = texels
+        .iter()
+        .flat_map(|texel| to_bytes(texel.color))
+        .collect();
+    let normal_map = texture(normals.collect(), TextureFormat::Rgba8Unorm);
+
+    let (emissive, emissive_texture) = if surface.kind == PlanetKind::Lava {
+        let glow = texels
+            .iter()
+            .flat_map(|texel| to_bytes(texel.glow))
+            .collect();
+        (
+            Color::rgb_linear(LAVA_EMISSIVE, LAVA_EMISSIVE, LAVA_EMISSIVE),
+            Some(images.add(texture(glow, TextureFormat::Rgba8UnormSrgb))),
+        )
+    } else {
+        (Color::BLACK, None)
+    };
+
+    StandardMaterial {
+        base_color_texture: Some(images.add(texture(color, TextureFormat::Rgba8UnormSrgb))),
+        normal_map_texture: Some(images.add(normal_map)),
+        emissive,
+        emissive_texture,
+        perceptual_roughness: roughness,
+        ..default()
+    }
+}
@@ -0,0 +1,180 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::{
+    calculate_acceleration_velocity, end_physics_tick, motion::BodyMotion, player::SelectedBody,
+    ship::Ship, view::SphereMeshes, AppState, GameSpeed, PhysicsSettings, Planet, PlanetBundle,
+    TIME_SPEED,
+};
+
+/// Satellites only break up near bodies at least this many times heavier
+const ROCHE_MASS_RATIO: f32 = 10.;
+/// Fluid body Roche limit, in primary radii times the cube root of the density ratio
+const ROCHE_COEFFICIENT: f32 = 2.44;
+const FRAGMENT_COUNT: usize = 12;
+/// Radius of the ring of fragments, in radii of the broken body
+const FRAGMENT_SPREAD: f32 = 3.;
+
+pub struct TidesPlugin;
+
+impl Plugin for TidesPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .add_systems(
+                FixedUpdate,
+                (
+                    roche_breakup.run_if(|physics: Res<PhysicsSettings>| physics.roche_breakup),
+                    tidal_dissipation
+                        .run_if(|physics: Res<PhysicsSettings>| physics.tidal_dissipation > 0.),
+                )
+                    .chain()
+                    .after(calculate_acceleration_velocity)
+                    .before(end_physics_tick)
+                    .run_if(in_state(AppState::Simulating)),
+            );
+    }
+}
+
+/// Piece of a body torn apart by tides, it doesn't break up again
+#[derive(Component)]
+pub struct Fragment;
+
+/// Fragments, bodies with a set motion and the player ship don't break up
+type Unbreakable = Or<(With<Fragment>, With<BodyMotion>, With<Ship>)>;
+
+/// Distance (km) inside which `primary` tears `satellite` apart
+fn roche_limit(primary: &Planet, satellite: &Planet) -> f32 {
+    ROCHE_COEFFICIENT * primary.radius * (primary.density / satellite.density).cbrt()
+}
+
+/// Body that pulls hardest on `satellite`, if it is heavy enough to raise tides on it
+fn dominant_body<'a>(
+    satellite: (Entity, &Planet),
+    bodies: impl IntoIterator<Item = (Entity, &'a Planet)>,
+) -> Option<(Entity, &'a Planet)> {
+    let (entity, satellite) = satellite;
+    let pull = |body: &Planet| body.mass() / body.position.distance_squared(satellite.position);
+
+    bodies
+        .into_iter()
+        .filter(|(other, _)| *other != entity)
+        .max_by(|(_, a), (_, b)| pull(a).total_cmp(&pull(b)))
+        .filter(|(_, primary)| primary.mass() >= ROCHE_MASS_RATIO * satellite.mass())
+}
+
+/// Replaces satellites inside the Roche limit of their primary with a ring of fragments.
+/// The fragments keep the mass, momentum, spin and charge of the body, orbital shear then spreads
+/// them along the orbit.
+fn roche_breakup(
+    mut commands: Commands,
+    sphere_meshes: Res<SphereMeshes>,
+    mut selected: ResMut<SelectedBody>,
+    planets_q: Query<(Entity, &Planet, &Handle<StandardMaterial>, Option<&Name>)>,
+    unbreakable_q: Query<(), Unbreakable>,
+) {
+    for (entity, satellite, material, name) in &planets_q {
+        if unbreakable_q.contains(entity) {
+            continue;
+        }
+        let Some((primary_entity, primary)) = dominant_body(
+            (entity, satellite),
+            planets_q.iter().map(|(other, planet, ..)| (other, planet)),
+        ) else {
+            continue;
+        };
+
+        let distance = satellite.position.distance(primary.position);
+        // Touching bodies are left to the collision handling
+        if distance >= roche_limit(primary, satellite)
+            || distance <= primary.radius + satellite.radius
+        {
+            continue;
+        }
+
+        let name = name.map_or("Body", |name| name.as_str());
+        info!("{name} broke up inside the Roche limit");
+
+        // Ring in the orbital plane, around the center of the body
+        let normal = (satellite.position - primary.position)
+            .cross(satellite.velocity - primary.velocity)
+            .try_normalize()
+            .unwrap_or(Vec3::Y);
+        let radial = (satellite.position - primary.position).normalize();
+        let radius = satellite.radius / (FRAGMENT_COUNT as f32).cbrt();
+        let spread = satellite.radius * FRAGMENT_SPREAD;
+        // Moved out if the inner fragment would be inside the primary, with a radius to spare
+        let center =
+            primary.position + radial * distance.max(primary.radius + 2. * radius + spread);
+
+        for i in 0..FRAGMENT_COUNT {
+            let angle = i as f32 / FRAGMENT_COUNT as f32 * TAU;
+            let offset = Quat::from_axis_angle(normal, angle) * radial * spread;
+            // Rigid rotation of the broken body
+            let velocity = satellite.velocity + satellite.angular_velocity.cross(offset);
+
+            let mut fragment = PlanetBundle::new(
+                &sphere_meshes,
+                radius,
+                satellite.density,
+                material.clone(),
+                center + offset,
+                Some(velocity),
+            )
+            .with_spin(satellite.orientation, satellite.angular_velocity)
+            .with_charge(satellite.charge / FRAGMENT_COUNT as f32);
+            fragment.planet.acceleration = satellite.acceleration;
+
+            commands.spawn((
+                fragment,
+                Name::new(format!("{name} fragment {}", i + 1)),
+                Fragment,
+            ));
+        }
+
+        commands.entity(entity).despawn_recursive();
+        if selected.0 == Some(entity) {
+            selected.0 = Some(primary_entity);
+        }
+    }
+}
+
+/// Tides raised on the satellite by its primary damp the radial part of its motion, which
+/// circularizes eccentric orbits. The rate falls off with the cube of the distance.
+/// Only the satellite is slowed, the primary is assumed much heavier.
+fn tidal_dissipation(
+    time: Res<Time>,
+    game_speed: Res<GameSpeed>,
+    physics: Res<PhysicsSettings>,
+    mut planets_q: Query<(Entity, &mut Planet, Has<BodyMotion>)>,
+) {
+    let dt = time.delta_seconds() * TIME_SPEED * game_speed.speed;
+
+    let bodies: Vec<(Entity, Planet)> = planets_q
+        .iter()
+        .map(|(entity, planet, _)| (entity, planet.clone()))
+        .collect();
+
+    for (entity, mut satellite, fixed) in &mut planets_q {
+        if fixed {
+            continue;
+        }
+        let Some((_, primary)) = dominant_body(
+            (entity, &satellite),
+            bodies.iter().map(|(other, planet)| (*other, planet)),
+        ) else {
+            continue;
+        };
+
+        let offset = satellite.position - primary.position;
+        let Some(radial) = offset.try_normalize() else {
+            continue;
+        };
+        let relative_velocity = satellite.velocity - primary.velocity;
+        let radial_speed = relative_velocity.dot(radial);
+
+        let rate = physics.tidal_dissipation * (primary.radius / offset.length()).powi(3);
+        let damping = 1. - (-rate * dt).exp();
+        satellite.velocity -= radial * radial_speed * damping;
+    }
+}
@@ -1,10 +1,16 @@
-use bevy::render::{
-    render_asset::RenderAssetUsages,
-    render_resource::{Extent3d, TextureDimension, TextureFormat},
-    texture::Image,
+use bevy::{
+    math::Vec3,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::Image,
+    },
 };
 
-pub const GRAVITY_CONSTANT: f32 = 6.67430e-11f32; // division its temporal
+pub const GRAVITY_CONSTANT: f32 = 6.67430e-20f32; // km³ / (kg s²)
+pub const KG_PER_KM3_PER_G_CM3: f32 = 1e12; // density g/cm³ to kg/km³
+pub const SPEED_OF_LIGHT: f32 = 299_792.5; // km/s
+pub const COULOMB_CONSTANT: f32 = 8.987_552; // km³ kg / (s² C²)
 
 // pub fn calculate_force(mass1: f32, mass2: f32, distance: f32) -> f32 {
 //     if distance > 1. {
@@ -14,6 +20,61 @@ pub const GRAVITY_CONSTANT: f32 = 6.67430e-11f32; // division its temporal
 //     }
 // }
 
+/// Pseudo random value in [0, 1] for a lattice point
+fn lattice_hash(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    let mut hash = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0x5bd1_e995);
+    hash ^= hash >> 15;
+
+    hash as f32 / u32::MAX as f32
+}
+
+/// Smooth 3D value noise in [0, 1]
+pub fn value_noise(point: Vec3, seed: u32) -> f32 {
+    let cell = point.floor();
+    let local = point - cell;
+    let weight = local * local * (3. - 2. * local);
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let corner = |dx: i32, dy: i32, dz: i32| lattice_hash(x + dx, y + dy, z + dz, seed);
+
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), weight.x),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), weight.x),
+            weight.y,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), weight.x),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), weight.x),
+            weight.y,
+        ),
+        weight.z,
+    )
+}
+
+/// Fractal noise in [0, 1], each octave doubles the frequency and halves the amplitude
+pub fn fbm(point: Vec3, seed: u32, octaves: u32) -> f32 {
+    let mut value = 0.;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.;
+    let mut total = 0.;
+
+    for octave in 0..octaves {
+        value += value_noise(point * frequency, seed.wrapping_add(octave)) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.;
+    }
+
+    value / total
+}
+
 /// Creates a colorful test pattern
 pub fn uv_debug_texture() -> Image {
     const TEXTURE_SIZE: usize = 8;
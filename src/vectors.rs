@@ -0,0 +1,147 @@
+use bevy::{prelude::*, transform::TransformSystem};
+
+use crate::{
+    input::{Action, ActionState},
+    view::ViewScale,
+    GameSpeed, PhysicsSettings, Planet, TIME_SPEED,
+};
+
+/// Arrows show how far the vector moves a body in this many real seconds
+const VECTOR_LOOKAHEAD: f32 = 10.;
+/// Above this many bodies the pair lines get too expensive and unreadable
+const MAX_FORCE_LINE_BODIES: usize = 100;
+const VELOCITY_COLOR: Color = Color::LIME_GREEN;
+const ACCELERATION_COLOR: Color = Color::ORANGE_RED;
+
+pub struct VectorsPlugin;
+
+impl Plugin for VectorsPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<VectorOverlay>()
+            .add_systems(Update, toggle_vector_overlay)
+            .add_systems(
+                PostUpdate,
+                (draw_body_vectors, draw_force_lines).after(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Debug arrows drawn on top of the bodies
+#[derive(Resource, Default)]
+pub struct VectorOverlay {
+    /// Velocity and net acceleration of each body
+    pub body_vectors: bool,
+    /// Line between every pair of bodies, colored by the force between them
+    pub force_lines: bool,
+}
+
+fn toggle_vector_overlay(actions: Res<ActionState>, mut overlay: ResMut<VectorOverlay>) {
+    if actions.just_pressed(Action::ToggleBodyVectors) {
+        overlay.body_vectors = !overlay.body_vectors;
+    }
+
+    if actions.just_pressed(Action::ToggleForceLines) {
+        overlay.force_lines = !overlay.force_lines;
+    }
+}
+
+fn draw_body_vectors(
+    mut gizmos: Gizmos,
+    overlay: Res<VectorOverlay>,
+    view_scale: Res<ViewScale>,
+    game_speed: Res<GameSpeed>,
+    planets_q: Query<(&GlobalTransform, &Planet)>,
+) {
+    if !overlay.body_vectors {
+        return;
+    }
+
+    // Simulated seconds the arrows cover
+    let lookahead = VECTOR_LOOKAHEAD * TIME_SPEED * game_speed.speed;
+
+    for (transform, planet) in &planets_q {
+        let center = transform.translation();
+        let radius = transform.compute_transform().scale.x;
+
+        // Velocity moves the body by v t, acceleration adds ½ a t² on top of it
+        for (vector, color) in [
+            (planet.velocity * lookahead, VELOCITY_COLOR),
+            (
+                planet.acceleration * (0.5 * lookahead * lookahead),
+                ACCELERATION_COLOR,
+            ),
+        ] {
+            let Some(direction) = vector.try_normalize() else {
+                continue;
+            };
+
+            // Start on the surface so short arrows aren't hidden inside the body
+            let start = center + direction * radius;
+            let length = view_scale
+                .to_world(planet.position + vector)
+                .distance(center);
+            gizmos.arrow(start, start + direction * length, color);
+        }
+    }
+}
+
+fn draw_force_lines(
+    mut gizmos: Gizmos,
+    overlay: Res<VectorOverlay>,
+    physics: Res<PhysicsSettings>,
+    planets_q: Query<(&GlobalTransform, &Planet)>,
+) {
+    if !overlay.force_lines {
+        return;
+    }
+
+    let bodies: Vec<(Vec3, &Planet)> = planets_q
+        .iter()
+        .map(|(transform, planet)| (transform.translation(), planet))
+        .collect();
+    if bodies.len() > MAX_FORCE_LINE_BODIES {
+        return;
+    }
+
+    let mut lines = Vec::new();
+    for (i, (start, planet)) in bodies.iter().enumerate() {
+        for (end, other) in &bodies[i + 1..] {
+            // Colliding bodies don't pull on each other, see `calculate_acceleration_velocity`
+            if planet.position.distance(other.position) < planet.radius + other.radius {
+                continue;
+            }
+
+            let force = physics
+                .gravity
+                .acceleration(planet.position, other.position, other.mass())
+                .length()
+                * planet.mass();
+            lines.push((*start, *end, force));
+        }
+    }
+
+    // Forces span many orders of magnitude, color them on a log scale. Forces that underflow
+    // to zero far away would stretch the scale to infinity, they get the low end. `None` when
+    // every force is zero.
+    let bounds = lines
+        .iter()
+        .map(|&(.., force)| force)
+        .filter(|&force| force > 0.)
+        .fold(None, |bounds, force| match bounds {
+            None => Some((force, force)),
+            Some((min, max)) => Some((force.min(min), force.max(max))),
+        });
+
+    for (start, end, force) in lines {
+        let t = match bounds {
+            Some((min_force, max_force)) if force > 0. && max_force > min_force => {
+                (force / min_force).ln() / (max_force / min_force).ln()
+            }
+            // All the forces are equal
+            Some(_) if force > 0. => 1.,
+            _ => 0.,
+        };
+        gizmos.line(start, end, Color::rgb(t, 0.2, 1. - t));
+    }
+}
@@ -0,0 +1,283 @@
+use bevy::{prelude::*, transform::TransformSystem, utils::HashMap};
+
+use crate::{
+    input::{Action, ActionState},
+    player::MainCamera,
+    Planet,
+};
+
+const SIZE_SCALE: f32 = 7. / 1000.; // world units per km
+/// On-screen radius in pixels above which each sphere subdivision is used, from the
+/// coarsest level up
+const LOD_PIXEL_RADII: [f32; 4] = [8., 24., 64., 160.];
+
+pub struct ViewPlugin;
+
+impl Plugin for ViewPlugin {
+    fn build(&self, app: &mut App) {
+        app //
+            .init_resource::<ViewScale>()
+            .init_resource::<SphereMeshes>()
+            .init_resource::<BodyMaterials>()
+            .add_systems(Update, change_view_scale)
+            .add_systems(
+                PostUpdate,
+                sync_planet_transforms.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// How physical positions and radii (km) are drawn in the world.
+/// Only affects rendering, the simulation never reads it.
+#[derive(Resource)]
+pub struct ViewScale {
+    /// World units per km
+    pub distance: f32,
+    /// Radius exaggeration on top of `distance`, 1 is true proportions
+    pub body_scale: f32,
+    /// Compress radial distance from the origin logarithmically
+    pub log_distance: bool,
+    /// World distance around which the log compression kicks in
+    pub log_reference: f32,
+    /// Bodies are never drawn smaller than this on screen, in pixels
+    pub min_pixel_radius: f32,
+}
+
+impl Default for ViewScale {
+    fn default() -> Self {
+        ViewScale {
+            distance: SIZE_SCALE,
+            body_scale: 1.,
+            log_distance: false,
+            log_reference: 500.,
+            min_pixel_radius: 2.,
+        }
+    }
+}
+
+impl ViewScale {
+    /// Physical position (km) to world translation
+    pub fn to_world(&self, position: Vec3) -> Vec3 {
+        let linear = position * self.distance;
+        if !self.log_distance {
+            return linear;
+        }
+
+        let r = linear.length();
+        if r <= f32::EPSILON {
+            return linear;
+        }
+
+        linear * (self.log_reference * (r / self.log_reference).ln_1p() / r)
+    }
+
+    /// World translation to physical position (km), inverse of [`ViewScale::to_world`]
+    pub fn to_physical(&self, translation: Vec3) -> Vec3 {
+        let mut linear = translation;
+        if self.log_distance {
+            let r = translation.length();
+            if r > f32::EPSILON {
+                linear *= self.log_reference * (r / self.log_reference).exp_m1() / r;
+            }
+        }
+
+        linear / self.distance
+    }
+
+    /// World space bounding box of the bodies, `None` without bodies
+    pub fn bounds<'a>(
+        &self,
+        planets: impl IntoIterator<Item = &'a Planet>,
+    ) -> Option<(Vec3, Vec3)> {
+        planets
+            .into_iter()
+            .map(|planet| self.to_world(planet.position))
+            .fold(None, |bounds, position| match bounds {
+                None => Some((position, position)),
+                Some((min, max)) => Some((position.min(min), position.max(max))),
+            })
+    }
+
+    /// Physical radius (km) to world radius, before the on-screen minimum
+    pub fn radius_to_world(&self, radius: f32) -> f32 {
+        radius * self.distance * self.body_scale
+    }
+}
+
+/// Unit spheres shared by every body, the `Transform` scales them to the body radius.
+/// Sharing them lets the renderer batch bodies drawn with the same material.
+#[derive(Resource)]
+pub struct SphereMeshes {
+    /// Icosphere subdivisions 1 to 5
+    levels: Vec<Handle<Mesh>>,
+}
+
+impl FromWorld for SphereMeshes {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let levels = (1..=5)
+            .map(|subdivisions| {
+                // Tangents for the normal maps of procedural surfaces
+                meshes.add(
+                    Sphere::new(1.)
+                        .mesh()
+                        .ico(subdivisions)
+                        .unwrap()
+                        .with_generated_tangents()
+                        .unwrap(),
+                )
+            })
+            .collect();
+
+        SphereMeshes { levels }
+    }
+}
+
+impl SphereMeshes {
+    /// Most detailed sphere, used until the first LOD update
+    pub fn finest(&self) -> Handle<Mesh> {
+        self.levels[self.levels.len() - 1].clone()
+    }
+
+    /// Sphere with `subdivisions` between 1 and 5
+    pub fn level(&self, subdivisions: usize) -> Handle<Mesh> {
+        self.levels[subdivisions.clamp(1, self.levels.len()) - 1].clone()
+    }
+
+    /// Sphere detailed enough for a body covering `pixel_radius` pixels on screen
+    fn for_pixel_radius(&self, pixel_radius: f32) -> &Handle<Mesh> {
+        let level = LOD_PIXEL_RADII
+            .iter()
+            .take_while(|&&radius| pixel_radius > radius)
+            .count();
+        &self.levels[level]
+    }
+}
+
+/// Plain body materials shared by color. Materials that get changed per body, like
+/// surfaces and stars, are copied first.
+#[derive(Resource, Default)]
+pub struct BodyMaterials(HashMap<[u8; 4], Handle<StandardMaterial>>);
+
+impl BodyMaterials {
+    /// Material of `color`, `material` builds it the first time the color is seen
+    pub fn get_or_add(
+        &mut self,
+        color: Color,
+        materials: &mut Assets<StandardMaterial>,
+        material: impl FnOnce(Color) -> StandardMaterial,
+    ) -> Handle<StandardMaterial> {
+        self.0
+            .entry(color.as_rgba_u8())
+            .or_insert_with(|| materials.add(material(color)))
+            .clone()
+    }
+}
+
+fn change_view_scale(actions: Res<ActionState>, mut view_scale: ResMut<ViewScale>) {
+    if actions.just_pressed(Action::ToggleLogDistance) {
+        view_scale.log_distance = !view_scale.log_distance;
+    }
+
+    if actions.just_pressed(Action::BodyScaleUp) {
+        view_scale.body_scale *= 2.;
+    }
+
+    if actions.just_pressed(Action::BodyScaleDown) {
+        view_scale.body_scale = (view_scale.body_scale / 2.).max(1.);
+    }
+}
+
+fn sync_planet_transforms(
+    view_scale: Res<ViewScale>,
+    sphere_meshes: Res<SphereMeshes>,
+    mut planets_query: Query<(&mut Transform, &mut Handle<Mesh>, &Planet)>,
+    camera_q: Query<(&GlobalTransform, &Camera, &Projection), With<MainCamera>>,
+) {
+    // World size of one pixel at unit distance from the camera
+    let pixel_size = camera_q
+        .get_single()
+        .ok()
+        .and_then(|(transform, camera, projection)| {
+            let Projection::Perspective(perspective) = projection else {
+                return None;
+            };
+            let viewport = camera.logical_viewport_size()?;
+
+            Some((
+                transform.translation(),
+                2. * (perspective.fov / 2.).tan() / viewport.y,
+            ))
+        });
+
+    for (mut transform, mut mesh, planet) in &mut planets_query {
+        transform.translation = view_scale.to_world(planet.position);
+        transform.rotation = planet.orientation;
+
+        // Body meshes are unit spheres
+        let mut radius = view_scale.radius_to_world(planet.radius);
+        if let Some((camera_pos, pixel_size)) = pixel_size {
+            let pixels_per_unit = 1. / (pixel_size * camera_pos.distance(transform.translation));
+            radius = radius.max(view_scale.min_pixel_radius / pixels_per_unit);
+
+            let lod = sphere_meshes.for_pixel_radius(radius * pixels_per_unit);
+            if *mesh != *lod {
+                *mesh = lod.clone();
+            }
+        }
+        transform.scale = Vec3::splat(radius);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(scale: &ViewScale, position: Vec3) {
+        let back = scale.to_physical(scale.to_world(position));
+        assert!(
+            back.distance(position) <= 1e-4 * position.length().max(1.),
+            "{position} came back as {back}"
+        );
+    }
+
+    const POSITIONS: [Vec3; 5] = [
+        Vec3::ZERO,
+        Vec3::new(1., 0., 0.),
+        Vec3::new(384_400., 0., 0.),
+        Vec3::new(-1e6, 2e5, 3e4),
+        Vec3::new(1.5e8, -1e8, 5e7),
+    ];
+
+    #[test]
+    fn linear_round_trip() {
+        let scale = ViewScale::default();
+        for position in POSITIONS {
+            assert_round_trip(&scale, position);
+        }
+    }
+
+    #[test]
+    fn log_round_trip() {
+        let scale = ViewScale {
+            log_distance: true,
+            ..default()
+        };
+        for position in POSITIONS {
+            assert_round_trip(&scale, position);
+        }
+    }
+
+    #[test]
+    fn log_compresses_far_distances() {
+        let scale = ViewScale {
+            log_distance: true,
+            ..default()
+        };
+        let near = scale.to_world(Vec3::X * 1e5).length();
+        let far = scale.to_world(Vec3::X * 1e8).length();
+
+        assert!(far > near);
+        assert!(far < ViewScale::default().to_world(Vec3::X * 1e8).length());
+    }
+}